@@ -0,0 +1,235 @@
+use std::{collections::HashMap, path::{Path, PathBuf}};
+
+use anyhow::Result;
+use hyper::{header::{HeaderValue, AUTHORIZATION}, HeaderMap};
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::error::{io_err, parse_err};
+
+pub const REDACTED: &str = "<redacted>";
+
+/// A password or token. It is never printed, not even by `Debug` or when serialized.
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new<S: ToString>(secret: S) -> Secret {
+        Secret(secret.to_string())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", REDACTED)
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Credentials {
+    Basic { username: String, password: Secret },
+    Bearer(Secret)
+}
+
+impl Credentials {
+    fn from_parts(username: Option<&str>, password: Option<&Secret>, token: Option<&Secret>) -> Option<Credentials> {
+        match (token, username) {
+            (Some(token), _) => Some(Credentials::Bearer(token.clone())),
+            (None, Some(username)) => Some(Credentials::Basic {
+                username: username.to_string(),
+                password: password.cloned().unwrap_or_else(|| Secret::new(""))
+            }),
+            (None, None) => None
+        }
+    }
+
+    /**
+     * `Authorization` header carrying these credentials, marked sensitive.
+     */
+    pub fn headers(&self) -> Result<HeaderMap> {
+        let value = match self {
+            Credentials::Basic { username, password } =>
+                format!("Basic {}", base64::encode(format!("{}:{}", username, password.expose()))),
+            Credentials::Bearer(token) => format!("Bearer {}", token.expose())
+        };
+
+        let mut value = HeaderValue::from_str(&value)
+            .map_err(|_| parse_err("credentials contain characters not allowed in an HTTP header"))?;
+        value.set_sensitive(true);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, value);
+
+        Ok(headers)
+    }
+}
+
+/// Inline credentials of a repository in the config file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RepoAuth {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<Secret>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<Secret>
+}
+
+/// Where credentials of repositories are looked up, besides the config file.
+#[derive(Debug, Clone, Default)]
+pub struct CredentialStore {
+    /// TOML file with a table of `username`/`password` or `token` per repository name.
+    pub credentials_path: PathBuf,
+    pub netrc_path: Option<PathBuf>
+}
+
+/**
+ * Prefix of environment variables of repository `name`, such as `MPT_GET_OURTEAM_` for `ourteam`.
+ */
+fn env_prefix(name: &str) -> String {
+    let name: String = name.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' }).collect();
+
+    format!("MPT_GET_{}_", name)
+}
+
+/**
+ * Find the `login` and `password` of `host` in a `.netrc` file, falling back to the `default` entry.
+ */
+fn parse_netrc(content: &str, host: &str) -> Option<(String, Option<String>)> {
+    let mut tokens = content.split_whitespace();
+    let mut entries: Vec<(Option<String>, Option<String>, Option<String>)> = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" => entries.push((tokens.next().map(String::from), None, None)),
+            "default" => entries.push((None, None, None)),
+            "login" => if let Some(entry) = entries.last_mut() { entry.1 = tokens.next().map(String::from) },
+            "password" => if let Some(entry) = entries.last_mut() { entry.2 = tokens.next().map(String::from) },
+            "account" => { tokens.next(); }
+            _ => {}
+        }
+    }
+
+    let find = |machine: Option<&str>| entries.iter().find(|entry| entry.0.as_deref() == machine);
+
+    find(Some(host))
+        .or_else(|| find(None))
+        .and_then(|(_, login, password)| Some((login.clone()?, password.clone())))
+}
+
+/**
+ * The credentials file holds secrets, refuse it if anyone else can read it.
+ */
+#[cfg(unix)]
+fn check_permissions(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = std::fs::metadata(path)?.permissions().mode();
+
+    if mode & 0o077 != 0 {
+        return Err(io_err(format!("{:?} is accessible by other users (mode {:o}), run chmod 600 on it", path, mode & 0o777)).into());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_: &Path) -> Result<()> {
+    Ok(())
+}
+
+impl CredentialStore {
+    /**
+     * Credentials of repository `name` at `host`, from the first source that has them:
+     * the config file, environment variables, the credentials file, then `.netrc`.
+     */
+    pub fn lookup(&self, name: &str, host: Option<&str>, inline: &RepoAuth) -> Result<Option<Credentials>> {
+        if let Some(creds) = Credentials::from_parts(inline.username.as_deref(), inline.password.as_ref(), inline.token.as_ref()) {
+            return Ok(Some(creds));
+        }
+
+        if !name.is_empty() {
+            let prefix = env_prefix(name);
+            let var = |key: &str| std::env::var(format!("{}{}", prefix, key)).ok();
+            let (username, password, token) = (var("USERNAME"), var("PASSWORD").map(Secret), var("TOKEN").map(Secret));
+
+            if let Some(creds) = Credentials::from_parts(username.as_deref(), password.as_ref(), token.as_ref()) {
+                return Ok(Some(creds));
+            }
+
+            if self.credentials_path.exists() {
+                check_permissions(&self.credentials_path)?;
+
+                let content = std::fs::read_to_string(&self.credentials_path)?;
+                let file: HashMap<String, RepoAuth> = toml::from_str(&content)
+                    .map_err(|err| parse_err(format!("failed to parse {:?}: {}", self.credentials_path, err)))?;
+
+                if let Some(auth) = file.get(name) {
+                    if let Some(creds) = Credentials::from_parts(auth.username.as_deref(), auth.password.as_ref(), auth.token.as_ref()) {
+                        return Ok(Some(creds));
+                    }
+                }
+            }
+        }
+
+        match (&self.netrc_path, host) {
+            (Some(path), Some(host)) if path.exists() => {
+                let content = std::fs::read_to_string(path)?;
+
+                Ok(parse_netrc(&content, host).map(|(username, password)| Credentials::Basic {
+                    username,
+                    password: Secret(password.unwrap_or_default())
+                }))
+            }
+
+            _ => Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn netrc() {
+        let netrc = "machine nexus.ourteam.com\n  login ci\n  password s3cret\n\ndefault login anonymous password guest\n";
+
+        assert_eq!(Some((String::from("ci"), Some(String::from("s3cret")))), parse_netrc(netrc, "nexus.ourteam.com"));
+        assert_eq!(Some((String::from("anonymous"), Some(String::from("guest")))), parse_netrc(netrc, "maven.aliyun.com"));
+        assert_eq!(None, parse_netrc("machine a.com login x", "b.com"));
+    }
+
+    #[test]
+    fn secrets_stay_hidden() {
+        let creds = Credentials::Basic { username: String::from("ci"), password: Secret::new("s3cret") };
+        let auth = RepoAuth { token: Some(Secret::new("t0ken")), ..RepoAuth::default() };
+
+        assert!(!format!("{:?}", creds).contains("s3cret"));
+        assert!(!toml::to_string(&auth).unwrap().contains("t0ken"));
+        assert_eq!("Basic Y2k6czNjcmV0", creds.headers().unwrap()[AUTHORIZATION]);
+    }
+
+    #[test]
+    fn lookup_order() {
+        let store = CredentialStore::default();
+        let inline = RepoAuth { username: Some(String::from("ci")), ..RepoAuth::default() };
+
+        std::env::set_var("MPT_GET_OUR_TEAM_TOKEN", "t0ken");
+
+        assert_eq!(Some(Credentials::Bearer(Secret::new("t0ken"))), store.lookup("our-team", None, &RepoAuth::default()).unwrap());
+        assert!(matches!(store.lookup("our-team", None, &inline).unwrap(), Some(Credentials::Basic { .. })));
+        assert_eq!(None, store.lookup("", None, &RepoAuth::default()).unwrap());
+    }
+}
@@ -0,0 +1,470 @@
+use std::{collections::BTreeMap, fs, io, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::checksum::{copy_hashed, hash_file, Algorithm, Checksums};
+use crate::error::io_err;
+use crate::index::PackageID;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+const BYTES_PER_MB: u64 = 1024 * 1024;
+
+/// `[cache]` section of the config file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheLimits {
+    /// Total size of the cache in MiB, least recently used artifacts are evicted beyond it.
+    pub max_size_mb: Option<u64>,
+
+    /// Artifacts unused for this many days are evicted.
+    pub max_age_days: Option<u64>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub sha256: String,
+    pub size: u64,
+
+    /// Seconds since the epoch when the artifact was last stored or restored.
+    pub last_used: u64,
+
+    /// Url of the repository the artifact was downloaded from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>
+}
+
+/// Cache entries by coordinate, such as `net.mamoe:chat-command:0.4.0:.mirai.jar`.
+type CacheIndex = BTreeMap<String, CacheEntry>;
+
+/// Artifacts removed from the cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Freed {
+    pub entries: usize,
+    pub bytes: u64
+}
+
+impl std::fmt::Display for Freed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} artifacts, {:.1} MiB", self.entries, self.bytes as f64 / BYTES_PER_MB as f64)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_secs()).unwrap_or(0)
+}
+
+#[cfg(unix)]
+fn lock_exclusive(file: &fs::File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn lock_exclusive(_: &fs::File) -> io::Result<()> {
+    Ok(())
+}
+
+/// Downloaded artifacts stored by SHA-256 under `sha256/`, and indexed by coordinate in `index.json`,
+/// so the same file is only downloaded once for every instance on this machine.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+    limits: CacheLimits
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf, limits: CacheLimits) -> Cache {
+        Cache { dir, limits }
+    }
+
+    pub fn key(pkg: &PackageID, version: &str, suffix: &str) -> String {
+        format!("{}:{}:{}", pkg, version, suffix)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn blob_path(&self, sha256: &str) -> PathBuf {
+        self.dir.join("sha256").join(&sha256[..2]).join(sha256)
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.dir.join("index.json")
+    }
+
+    /**
+     * Take the advisory lock of the cache, which is released when the returned file is dropped.
+     * It is held while `index.json` is read, modified and written back and while files are deleted,
+     * so instances sharing the cache neither lose each other's entries nor delete each other's files.
+     */
+    fn lock(&self) -> Result<fs::File> {
+        fs::create_dir_all(&self.dir)?;
+
+        let path = self.dir.join("index.lock");
+        let file = fs::OpenOptions::new().create(true).truncate(false).write(true).open(&path)?;
+
+        lock_exclusive(&file).map_err(|err| io_err(format!("failed to lock {:?}: {}", path, err)))?;
+
+        Ok(file)
+    }
+
+    fn load(&self) -> Result<CacheIndex> {
+        let path = self.index_path();
+
+        if !path.exists() {
+            return Ok(CacheIndex::new());
+        }
+
+        serde_json::from_slice(&fs::read(&path)?)
+            .map_err(|err| io_err(format!("failed to parse {:?}: {}, run mpt-get clean to reset the cache", path, err)).into())
+    }
+
+    fn save(&self, index: &CacheIndex) -> Result<()> {
+        let path = self.index_path();
+        let tmp = path.with_extension("json.tmp");
+
+        fs::create_dir_all(&self.dir)?;
+        fs::write(&tmp, serde_json::to_string_pretty(index)?)?;
+        fs::rename(&tmp, &path)?;
+
+        Ok(())
+    }
+
+    /**
+     * Copy the cached artifact `key` to `dest`, returns its checksums and the repository it was downloaded from.
+     * `None` if it is not cached, or if the cached file is damaged, which is then dropped with every entry sharing it.
+     */
+    pub fn restore(&self, key: &str, dest: &Path) -> Result<Option<(Checksums, Option<String>)>> {
+        if !self.index_path().exists() {
+            return Ok(None);
+        }
+
+        let _lock = self.lock()?;
+        let mut index = self.load()?;
+
+        let entry = match index.get_mut(key) {
+            Some(entry) => entry,
+            None => return Ok(None)
+        };
+
+        let blob = self.blob_path(&entry.sha256);
+
+        let actual = if blob.exists() { Some(copy_hashed(&blob, dest)?) } else { None };
+
+        match actual {
+            Some(actual) if actual.get(&Algorithm::Sha256) == Some(&entry.sha256) => {
+                entry.last_used = now();
+                let repo = entry.repo.clone();
+                self.save(&index)?;
+
+                Ok(Some((actual, repo)))
+            }
+
+            damaged => {
+                if damaged.is_some() {
+                    fs::remove_file(dest)?;
+                }
+
+                let sha256 = entry.sha256.clone();
+                index.retain(|_, entry| entry.sha256 != sha256);
+                self.collect(&index)?;
+                self.save(&index)?;
+
+                Ok(None)
+            }
+        }
+    }
+
+    /**
+     * Size of the cached artifact `key`, `None` if it is not cached.
+     */
+    pub fn size(&self, key: &str) -> Option<u64> {
+        let entry = self.load().ok()?.remove(key)?;
+
+        Some(entry.size).filter(|_| self.blob_path(&entry.sha256).exists())
+    }
+
+    /**
+     * Store a copy of the verified artifact `file`, downloaded from the repository at `repo`, as `key`,
+     * then evict artifacts beyond the limits.
+     */
+    pub fn store(&self, key: &str, file: &Path, repo: &str) -> Result<()> {
+        let sha256 = hash_file(file)?
+            .remove(&Algorithm::Sha256)
+            .expect("sha256 is always computed");
+
+        let blob = self.blob_path(&sha256);
+        let tmp = self.dir.join(format!("{}.{}.tmp", sha256, std::process::id()));
+
+        // copied before taking the lock, the blob is only moved into place together with its entry
+        fs::create_dir_all(&self.dir)?;
+
+        let copied = if blob.exists() { None } else { Some(copy_hashed(file, &tmp)?) };
+
+        if let Some(actual) = &copied {
+            if actual.get(&Algorithm::Sha256) != Some(&sha256) {
+                fs::remove_file(&tmp)?;
+                return Err(io_err(format!("{:?} changed while it was cached", file)).into());
+            }
+        }
+
+        let _lock = self.lock()?;
+
+        if copied.is_some() {
+            if blob.exists() {
+                fs::remove_file(&tmp)?;
+            } else {
+                if let Some(parent) = blob.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+
+                fs::rename(&tmp, &blob)?;
+            }
+        }
+
+        // another instance may have collected the blob since it was seen
+        if !blob.exists() {
+            return Err(io_err(format!("{:?} was removed while {} was cached, try again", blob, key)).into());
+        }
+
+        let mut index = self.load()?;
+        index.insert(key.to_string(), CacheEntry { sha256, size: fs::metadata(&blob)?.len(), last_used: now(), repo: Some(repo.to_string()) });
+
+        self.evict(&mut index);
+        self.collect(&index)?;
+        self.save(&index)
+    }
+
+    /**
+     * Remove entries beyond the age and size limits, least recently used first.
+     */
+    fn evict(&self, index: &mut CacheIndex) -> Freed {
+        let mut freed = Freed::default();
+
+        if let Some(days) = self.limits.max_age_days {
+            let oldest = now().saturating_sub(days * SECONDS_PER_DAY);
+
+            index.retain(|_, entry| {
+                let keep = entry.last_used >= oldest;
+
+                if !keep {
+                    freed.entries += 1;
+                    freed.bytes += entry.size;
+                }
+
+                keep
+            });
+        }
+
+        if let Some(max_size) = self.limits.max_size_mb.map(|mb| mb * BYTES_PER_MB) {
+            let mut by_age: Vec<(String, u64, u64)> = index.iter().map(|(key, entry)| (key.clone(), entry.last_used, entry.size)).collect();
+            by_age.sort_by_key(|(_, last_used, _)| *last_used);
+
+            let mut size: u64 = by_age.iter().map(|(_, _, size)| size).sum();
+
+            for (key, _, entry_size) in by_age {
+                if size <= max_size {
+                    break;
+                }
+
+                index.remove(&key);
+                size -= entry_size;
+                freed.entries += 1;
+                freed.bytes += entry_size;
+            }
+        }
+
+        freed
+    }
+
+    /**
+     * Delete files no entry of `index` refers to.
+     */
+    fn collect(&self, index: &CacheIndex) -> Result<()> {
+        let blobs = self.dir.join("sha256");
+
+        if !blobs.exists() {
+            return Ok(());
+        }
+
+        for prefix in fs::read_dir(&blobs)? {
+            let prefix = prefix?.path();
+
+            for blob in fs::read_dir(&prefix)? {
+                let blob = blob?.path();
+                let name = blob.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+
+                if !index.values().any(|entry| entry.sha256 == name) {
+                    fs::remove_file(&blob)?;
+                }
+            }
+
+            if fs::read_dir(&prefix)?.next().is_none() {
+                fs::remove_dir(&prefix)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Remove artifacts whose package and version are not `referenced`, and those beyond the limits.
+     */
+    pub fn prune(&self, referenced: impl Fn(&PackageID, &str) -> bool) -> Result<Freed> {
+        let _lock = self.lock()?;
+        let mut index = self.load()?;
+        let mut freed = Freed::default();
+
+        index.retain(|key, entry| {
+            let mut parts = key.splitn(4, ':');
+            let keep = match (parts.next(), parts.next(), parts.next()) {
+                (Some(domain), Some(name), Some(version)) =>
+                    referenced(&PackageID { domain: domain.to_string(), name: name.to_string() }, version),
+                _ => false
+            };
+
+            if !keep {
+                freed.entries += 1;
+                freed.bytes += entry.size;
+            }
+
+            keep
+        });
+
+        let evicted = self.evict(&mut index);
+
+        self.collect(&index)?;
+        self.save(&index)?;
+
+        Ok(Freed { entries: freed.entries + evicted.entries, bytes: freed.bytes + evicted.bytes })
+    }
+
+    /**
+     * Remove every cached artifact.
+     */
+    pub fn clear(&self) -> Result<Freed> {
+        if !self.dir.exists() {
+            return Ok(Freed::default());
+        }
+
+        let _lock = self.lock()?;
+        let index = self.load().unwrap_or_default();
+        let freed = Freed {
+            entries: index.len(),
+            bytes: index.values().map(|entry| entry.size).sum()
+        };
+
+        fs::remove_dir_all(&self.dir)?;
+
+        Ok(freed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(name: &str, limits: CacheLimits) -> (Cache, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("mpt-get-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        (Cache::new(dir.join("cache"), limits), dir)
+    }
+
+    fn pid(id: &str) -> PackageID {
+        id.parse().unwrap()
+    }
+
+    #[test]
+    fn store_and_restore() {
+        let (cache, dir) = cache("restore", CacheLimits::default());
+        let file = dir.join("chat-command-0.4.0.jar");
+        fs::write(&file, b"plugin").unwrap();
+
+        let key = Cache::key(&pid("net.mamoe:chat-command"), "0.4.0", ".jar");
+        cache.store(&key, &file, "https://maven.aliyun.com/repository/public").unwrap();
+        cache.store(&Cache::key(&pid("net.mamoe:chat-command"), "0.4.1", ".jar"), &file, "https://repo.maven.apache.org/maven2").unwrap();
+
+        let restored = dir.join("restored.jar");
+        let (sums, repo) = cache.restore(&key, &restored).unwrap().unwrap();
+
+        assert_eq!(b"plugin", &fs::read(&restored).unwrap()[..]);
+        assert_eq!(Some("https://maven.aliyun.com/repository/public"), repo.as_deref());
+        assert_eq!(Some(&cache.load().unwrap()[&key].sha256), sums.get(&Algorithm::Sha256));
+        assert_eq!(None, cache.restore("net.mamoe:chat-command:0.5.0:.jar", &restored).unwrap());
+        assert_eq!(Some(6), cache.size(&key));
+        assert_eq!(None, cache.size("net.mamoe:chat-command:0.5.0:.jar"));
+
+        // a damaged file is dropped instead of being installed
+        fs::write(cache.blob_path(&cache.load().unwrap()[&key].sha256), b"tampered").unwrap();
+
+        assert_eq!(None, cache.restore(&key, &restored).unwrap());
+        assert!(cache.load().unwrap().is_empty());
+        assert!(!restored.exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn shared_by_instances() {
+        let (cache, dir) = cache("shared", CacheLimits::default());
+
+        let stores: Vec<_> = (0..4).map(|instance| {
+            let (cache, dir) = (cache.clone(), dir.clone());
+
+            std::thread::spawn(move || {
+                for version in 0..10 {
+                    let file = dir.join(format!("bot-{}-{}.jar", instance, version));
+                    fs::write(&file, format!("{} {}", instance, version)).unwrap();
+
+                    cache.store(&format!("com.ourteam:bot-{}:{}:.jar", instance, version), &file, "").unwrap();
+                }
+            })
+        }).collect();
+
+        for store in stores {
+            store.join().unwrap();
+        }
+
+        let index = cache.load().unwrap();
+
+        assert_eq!(40, index.len());
+        assert!(index.values().all(|entry| cache.blob_path(&entry.sha256).exists()));
+        assert!(!fs::read_dir(&cache.dir).unwrap().any(|file| file.unwrap().path().extension() == Some("tmp".as_ref())));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn prune_and_limits() {
+        let (cache, dir) = cache("prune", CacheLimits { max_size_mb: Some(1), max_age_days: None });
+        let (small, large) = (dir.join("small.jar"), dir.join("large.jar"));
+        fs::write(&small, vec![1; 1024]).unwrap();
+        fs::write(&large, vec![2; 1024 * 1024]).unwrap();
+
+        cache.store("net.mamoe:chat-command:0.3.1:.jar", &small, "").unwrap();
+        cache.store("com.ourteam:bot:1.0:.jar", &small, "").unwrap();
+
+        let freed = cache.prune(|pid, version| pid.name == "bot" && version == "1.0").unwrap();
+        assert_eq!(Freed { entries: 1, bytes: 1024 }, freed);
+        assert_eq!(vec!["com.ourteam:bot:1.0:.jar"], cache.load().unwrap().keys().collect::<Vec<_>>());
+
+        // storing beyond 1 MiB evicts the least recently used artifact
+        cache.store("com.ourteam:bot:2.0:.jar", &large, "").unwrap();
+        assert_eq!(vec!["com.ourteam:bot:2.0:.jar"], cache.load().unwrap().keys().collect::<Vec<_>>());
+        assert_eq!(1, fs::read_dir(cache.dir.join("sha256")).unwrap().count());
+
+        assert_eq!(1, cache.clear().unwrap().entries);
+        assert!(!cache.dir.exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,198 @@
+use std::{collections::BTreeMap, fmt::Display, fs::File, io::{Read, Write}, path::Path};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::error::{checksum_err, Error};
+
+/// Checksum algorithms published by Maven repositories, from weakest to strongest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 4] = [Algorithm::Md5, Algorithm::Sha1, Algorithm::Sha256, Algorithm::Sha512];
+
+    /**
+     * Extension of the sidecar file, such as `.sha1` for `foo.jar.sha1`.
+     */
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Algorithm::Md5 => ".md5",
+            Algorithm::Sha1 => ".sha1",
+            Algorithm::Sha256 => ".sha256",
+            Algorithm::Sha512 => ".sha512"
+        }
+    }
+}
+
+impl Display for Algorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", &self.extension()[1..])
+    }
+}
+
+/// Hex encoded digests by algorithm.
+pub type Checksums = BTreeMap<Algorithm, String>;
+
+/// Computes every supported digest of a stream at once.
+pub struct Hasher {
+    md5: md5::Context,
+    sha1: Sha1,
+    sha256: Sha256,
+    sha512: Sha512
+}
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Hasher {
+            md5: md5::Context::new(),
+            sha1: Sha1::new(),
+            sha256: Sha256::new(),
+            sha512: Sha512::new()
+        }
+    }
+}
+
+impl Hasher {
+    pub fn update(&mut self, data: &[u8]) {
+        self.md5.consume(data);
+        self.sha1.update(data);
+        self.sha256.update(data);
+        self.sha512.update(data);
+    }
+
+    pub fn finish(self) -> Checksums {
+        let mut sums = Checksums::new();
+
+        sums.insert(Algorithm::Md5, format!("{:x}", self.md5.compute()));
+        sums.insert(Algorithm::Sha1, hex(&self.sha1.finalize()));
+        sums.insert(Algorithm::Sha256, hex(&self.sha256.finalize()));
+        sums.insert(Algorithm::Sha512, hex(&self.sha512.finalize()));
+
+        sums
+    }
+}
+
+/**
+ * Copy `src` to `dest`, returns the checksums of the copied content.
+ */
+pub fn copy_hashed(src: &Path, dest: &Path) -> Result<Checksums> {
+    let mut input = File::open(src)?;
+    let mut output = File::create(dest)?;
+    let mut hasher = Hasher::default();
+    let mut buffer = vec![0; 64 * 1024];
+
+    loop {
+        let read = input.read(&mut buffer)?;
+
+        if read == 0 {
+            return Ok(hasher.finish());
+        }
+
+        output.write_all(&buffer[..read])?;
+        hasher.update(&buffer[..read]);
+    }
+}
+
+/**
+ * Checksums of the content of `path`.
+ */
+pub fn hash_file(path: &Path) -> Result<Checksums> {
+    let mut input = File::open(path)?;
+    let mut hasher = Hasher::default();
+    let mut buffer = vec![0; 64 * 1024];
+
+    loop {
+        let read = input.read(&mut buffer)?;
+
+        if read == 0 {
+            return Ok(hasher.finish());
+        }
+
+        hasher.update(&buffer[..read]);
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/**
+ * Extract the digest from a sidecar file. Some repositories append the file name after the digest.
+ */
+pub fn parse_sidecar(content: &str) -> Option<String> {
+    let digest = content.split_whitespace().next()?.to_lowercase();
+
+    if !digest.is_empty() && digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(digest)
+    } else {
+        None
+    }
+}
+
+/**
+ * Compare `expected` digests against `actual`, `source` tells where the expected ones came from.
+ * Returns how many digests were checked.
+ */
+pub fn verify(name: &str, source: &str, expected: &Checksums, actual: &Checksums) -> Result<usize, Error> {
+    let mut checked = 0;
+
+    for (algo, digest) in expected {
+        if let Some(actual) = actual.get(algo) {
+            if !actual.eq_ignore_ascii_case(digest) {
+                return Err(checksum_err(format!("{} mismatch for {} ({}): expected {}, got {}", algo, name, source, digest, actual)));
+            }
+
+            checked += 1;
+        }
+    }
+
+    Ok(checked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digest_bytes() {
+        let mut hasher = Hasher::default();
+        hasher.update(b"mirai");
+
+        let sums = hasher.finish();
+
+        assert_eq!("e203cadb3aafcc78216784f1b245bfbcedd314f3", sums[&Algorithm::Sha1]);
+        assert_eq!("c631dac97d3f6112e92c51af79b3ed4a", sums[&Algorithm::Md5]);
+        assert_eq!(64, sums[&Algorithm::Sha256].len());
+        assert_eq!(128, sums[&Algorithm::Sha512].len());
+    }
+
+    #[test]
+    fn verify_mismatch() {
+        let mut hasher = Hasher::default();
+        hasher.update(b"mirai");
+
+        let actual = hasher.finish();
+        let mut expected = Checksums::new();
+
+        expected.insert(Algorithm::Sha1, actual[&Algorithm::Sha1].to_uppercase());
+        assert_eq!(Ok(1), verify("a.jar", "a.jar.sha1", &expected, &actual).map_err(|err| err.to_string()));
+
+        expected.insert(Algorithm::Md5, String::from("00"));
+        assert!(verify("a.jar", "a.jar.md5", &expected, &actual).is_err());
+    }
+
+    #[test]
+    fn sidecar_format() {
+        assert_eq!(Some(String::from("abc123")), parse_sidecar("ABC123  mirai-console-2.4.0.jar\n"));
+        assert_eq!(None, parse_sidecar("<html>not found</html>"));
+    }
+}
@@ -1,36 +1,143 @@
 use std::path::PathBuf;
 
-use serde::Deserialize;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
 
-use crate::{index::{MirrorRepo, Updater}, logger::Logger};
+use crate::{auth::CredentialStore, cache::{Cache, CacheLimits}, error::parse_err, get::SourceRepo, index::{IndexPolicy, MirrorRepo, Updater}, install::Installer, logger::Logger, net::{DownloadPolicy, NetworkConfig, RetryPolicy, TlsConfig}, pom::LibraryPolicy, proxy::{self, ProxySettings}, signature::SignaturePolicy};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub mirror_repo: String,
     pub source_repo: String,
+
+    /// Maven repositories tried in order, replaces `source_repo` when not empty.
+    pub repositories: Vec<SourceRepo>,
     pub index_path: PathBuf,
     pub package_path: PathBuf,
-    pub proxy: Option<String>
+
+    /// Where runtime libraries of packages are placed, such as the `plugin-shared-libraries` of mirai-console.
+    pub library_path: PathBuf,
+    pub state_path: PathBuf,
+
+    /// Downloaded artifacts shared by every instance, see [`Cache`].
+    pub cache_path: PathBuf,
+    pub keyring_path: PathBuf,
+
+    /// Credentials of private repositories by repository name, must only be readable by its owner.
+    pub credentials_path: PathBuf,
+
+    /// Proxy url such as `http://proxy.corp:3128` or `socks5h://127.0.0.1:1080`, overrides `HTTP_PROXY` and `HTTPS_PROXY`.
+    #[serde(serialize_with = "proxy::serialize_redacted")]
+    pub proxy: Option<String>,
+
+    /// Hosts reached without the proxy, in addition to `NO_PROXY`.
+    pub no_proxy: Vec<String>,
+    pub signature: SignaturePolicy,
+    pub index: IndexPolicy,
+    pub tls: TlsConfig,
+    pub retry: RetryPolicy,
+    pub download: DownloadPolicy,
+    pub network: NetworkConfig,
+    pub libraries: LibraryPolicy,
+    pub cache: CacheLimits
+}
+
+fn mpt_dir() -> PathBuf {
+    let mut mpt_dir = dirs::home_dir().unwrap_or(PathBuf::from("~"));
+    mpt_dir.push(".mpt-get");
+    mpt_dir
 }
 
 impl Config {
+    pub fn path() -> PathBuf {
+        let mut path = mpt_dir();
+        path.push("config.toml");
+        path
+    }
+
+    /**
+     * Read `~/.mpt-get/config.toml`, missing fields (or a missing file) take default values.
+     */
+    pub fn load() -> Result<Config> {
+        let path = Config::path();
+
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        let content = std::fs::read_to_string(&path)?;
+        let config = toml::from_str(&content)
+            .map_err(|err| parse_err(format!("failed to parse {:?}: {}", path, err)))?;
+
+        Ok(config)
+    }
+
+    /**
+     * The effective configuration as TOML, passwords and tokens are redacted.
+     */
+    pub fn to_toml(&self) -> Result<String> {
+        let value = toml::Value::try_from(self)?;
+
+        Ok(toml::to_string(&value)?)
+    }
+
+    pub fn credential_store(&self) -> CredentialStore {
+        let netrc_path = std::env::var_os("NETRC")
+            .map(PathBuf::from)
+            .or_else(|| dirs::home_dir().map(|home| home.join(".netrc")));
+
+        CredentialStore {
+            credentials_path: self.credentials_path.clone(),
+            netrc_path
+        }
+    }
+
+    pub fn proxy_settings(&self) -> ProxySettings {
+        ProxySettings::new(self.proxy.clone(), self.no_proxy.clone())
+    }
+
     pub fn mirror_repo(&self) -> MirrorRepo {
         MirrorRepo::new(&self.mirror_repo)
     }
 
+    pub fn source_repos(&self) -> Vec<SourceRepo> {
+        if self.repositories.is_empty() {
+            vec![SourceRepo::from_url(&self.source_repo)]
+        } else {
+            self.repositories.clone()
+        }
+    }
+
     pub fn updater<Log: Logger>(&self) -> Updater<Log> {
         Updater::new(self.mirror_repo(), self.index_path.clone())
+            .verify_commits(self.keyring_path.clone(), self.index.clone())
+            .limit_rate(self.download.limit_rate)
+            .proxy(self.proxy_settings())
+    }
+
+    pub fn installer<Log: Logger>(&self) -> Installer<Log> {
+        Installer::new(self.source_repos(), self.index_path.clone(), self.package_path.clone(), self.state_path.clone())
+            .signatures(self.keyring_path.clone(), self.signature)
+            .tls(self.tls.clone())
+            .proxy(self.proxy_settings())
+            .network(self.network.clone())
+            .retry(self.retry)
+            .downloads(self.download)
+            .credentials(self.credential_store())
+            .libraries(self.library_path.clone(), self.libraries.clone())
+            .cache(Cache::new(self.cache_path.clone(), self.cache))
     }
 }
 
 impl Default for Config {
     fn default() -> Self {
-        let mut mpt_dir = dirs::home_dir().unwrap_or(PathBuf::from("~"));
-        mpt_dir.push(".mpt-get");
+        let mpt_dir = mpt_dir();
 
         Config {
-            mirror_repo: "http://gitee.com/peratx/mirai-repo.git".to_string(),
-            source_repo: "http://maven.aliyun.com/repository/public".to_string(),
+            mirror_repo: "https://gitee.com/peratx/mirai-repo.git".to_string(),
+            source_repo: "https://maven.aliyun.com/repository/public".to_string(),
+            repositories: Vec::new(),
             index_path: {
                 let mut index_path = mpt_dir.clone();
                 index_path.push("index");
@@ -41,8 +148,41 @@ impl Default for Config {
                 package_path.push("packages");
                 package_path
             },
+            library_path: {
+                let mut library_path = mpt_dir.clone();
+                library_path.push("libraries");
+                library_path
+            },
+            state_path: {
+                let mut state_path = mpt_dir.clone();
+                state_path.push("installed.json");
+                state_path
+            },
+            cache_path: {
+                let mut cache_path = mpt_dir.clone();
+                cache_path.push("cache");
+                cache_path
+            },
+            keyring_path: {
+                let mut keyring_path = mpt_dir.clone();
+                keyring_path.push("keyring.json");
+                keyring_path
+            },
+            credentials_path: {
+                let mut credentials_path = mpt_dir.clone();
+                credentials_path.push("credentials.toml");
+                credentials_path
+            },
             proxy: None,
-
+            no_proxy: Vec::new(),
+            signature: SignaturePolicy::default(),
+            index: IndexPolicy::default(),
+            tls: TlsConfig::default(),
+            retry: RetryPolicy::default(),
+            download: DownloadPolicy::default(),
+            network: NetworkConfig::default(),
+            libraries: LibraryPolicy::default(),
+            cache: CacheLimits::default()
         }
     }
-}
\ No newline at end of file
+}
@@ -2,11 +2,19 @@ use std::fmt::Display;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ErrorKind {
     Index,
     IO,
-    Parse
+    Parse,
+    Install,
+    Resolve,
+    Checksum,
+    Signature,
+    Network,
+
+    /// A transient failure while talking to a server, worth retrying.
+    Transfer
 }
 
 #[derive(Debug)]
@@ -19,12 +27,24 @@ impl std::error::Error for Error {
     
 }
 
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let kind_human = match self.kind {
             ErrorKind::Index => "updating index",
             ErrorKind::IO => "I/O",
-            ErrorKind::Parse => "parsing"
+            ErrorKind::Parse => "parsing",
+            ErrorKind::Install => "installing",
+            ErrorKind::Resolve => "resolving dependencies",
+            ErrorKind::Checksum => "verifying checksum",
+            ErrorKind::Signature => "verifying signature",
+            ErrorKind::Network => "connecting",
+            ErrorKind::Transfer => "downloading"
         };
 
         write!(f, "Error occursed when {}: {}", kind_human, self.message)
@@ -52,6 +72,48 @@ pub fn parse_err<S: ToString>(message: S) -> Error {
     }
 }
 
+pub fn install_err<S: ToString>(message: S) -> Error {
+    Error {
+        kind: ErrorKind::Install,
+        message: message.to_string()
+    }
+}
+
+pub fn resolve_err<S: ToString>(message: S) -> Error {
+    Error {
+        kind: ErrorKind::Resolve,
+        message: message.to_string()
+    }
+}
+
+pub fn checksum_err<S: ToString>(message: S) -> Error {
+    Error {
+        kind: ErrorKind::Checksum,
+        message: message.to_string()
+    }
+}
+
+pub fn signature_err<S: ToString>(message: S) -> Error {
+    Error {
+        kind: ErrorKind::Signature,
+        message: message.to_string()
+    }
+}
+
+pub fn network_err<S: ToString>(message: S) -> Error {
+    Error {
+        kind: ErrorKind::Network,
+        message: message.to_string()
+    }
+}
+
+pub fn transfer_err<S: ToString>(message: S) -> Error {
+    Error {
+        kind: ErrorKind::Transfer,
+        message: message.to_string()
+    }
+}
+
 pub trait ToError {
     fn to_index_err<S: ToString>(&self, message: S) -> Error {
         index_err(message)
@@ -1,176 +1,961 @@
-use std::str::FromStr;
-use std::{fs::File, io::stdout, path::Path};
-use std::{io::Write, iter::FromIterator, net::TcpListener, path::PathBuf, time::Duration};
-use std::convert::TryFrom;
+use std::{collections::BTreeMap, str::FromStr};
+use std::{fs::{File, OpenOptions}, io::Read, path::Path};
+use std::{io::Write, net::TcpListener, path::PathBuf};
+use std::sync::Arc;
 
 use anyhow::Result;
 use hyper::{
     body::{Buf, HttpBody},
-    Client, Uri,
+    header::{HeaderName, HeaderValue, CONTENT_LENGTH, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE},
+    Body, HeaderMap, Response, StatusCode, Uri,
 };
+use serde::{Deserialize, Serialize};
 use tokio::runtime;
 
+use crate::checksum::{self, copy_hashed, Algorithm, Checksums, Hasher};
+use crate::error::{checksum_err, install_err, parse_err, signature_err, transfer_err};
+use crate::signature::{Action, Keyring, SignaturePolicy, Verdict};
 use crate::index::PackageID;
+use crate::metadata::Metadata;
+use crate::auth::{CredentialStore, RepoAuth, Secret};
+use crate::cache::Cache;
+use crate::net::{is_transient, HostLimiter, HttpClient, NetworkConfig, RateLimiter, RetryPolicy, TlsConfig};
+use crate::progress::{self, DownloadObserver, Phase, Progress};
+use crate::proxy::ProxySettings;
 
-#[derive(Debug)]
+/// Artifact suffixes of Mirai plugins, tried in order when the index declares none.
+pub const PROBED_SUFFIXES: [&str; 3] = [".mirai.jar", "-all.jar", ".jar"];
+
+/// A Maven repository, an entry of `[[repositories]]` in the config file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SourceRepo {
+    #[serde(default)]
+    name: String,
     url: String,
+
+    /// Package patterns served by this repository, such as `com.ourteam:*`. Every package if empty.
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+
+    /// Extra headers sent with every request to this repository, such as `Private-Token`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    headers: BTreeMap<String, Secret>,
+
+    #[serde(flatten)]
+    auth: RepoAuth,
 }
 
 impl SourceRepo {
     pub fn from_url<S: ToString>(url: S) -> Self {
         SourceRepo {
-            url: url.to_string()
+            name: String::new(),
+            url: url.to_string(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            headers: BTreeMap::new(),
+            auth: RepoAuth::default()
         }
     }
 
+    /**
+     * Headers for this repository: `Authorization` if it needs credentials, and its extra `headers`,
+     * all marked sensitive.
+     */
+    pub fn headers(&self, store: &CredentialStore) -> Result<HeaderMap> {
+        let host = Uri::from_str(&self.url).ok().and_then(|uri| uri.host().map(String::from));
+
+        let mut headers = match store.lookup(&self.name, host.as_deref(), &self.auth)? {
+            Some(creds) => creds.headers()?,
+            None => HeaderMap::new()
+        };
+
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_str(name)
+                .map_err(|_| parse_err(format!("invalid header name {:?} for repository {}", name, self)))?;
+            let mut value = HeaderValue::from_str(value.expose())
+                .map_err(|_| parse_err(format!("header {} for repository {} contains characters not allowed in an HTTP header", name, self)))?;
+            value.set_sensitive(true);
+
+            headers.insert(name, value);
+        }
+
+        Ok(headers)
+    }
+
     pub fn url(&self) -> &String {
         &self.url
     }
 
+    /**
+     * Directory of a local repository, such as `~/.m2/repository`, given as a path or a `file://` url.
+     */
+    pub fn local_dir(&self) -> Option<PathBuf> {
+        if let Some(path) = self.url.strip_prefix("file://") {
+            Some(PathBuf::from(path))
+        } else if let Some(path) = self.url.strip_prefix("~/") {
+            dirs::home_dir().map(|home| home.join(path))
+        } else if Path::new(&self.url).is_absolute() {
+            Some(PathBuf::from(&self.url))
+        } else {
+            None
+        }
+    }
+
+    /**
+     * Whether `pid` may be downloaded from this repository, according to `include` and `exclude`.
+     */
+    pub fn serves(&self, pid: &PackageID) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|pattern| pid.matches(pattern)))
+            && !self.exclude.iter().any(|pattern| pid.matches(pattern))
+    }
+
     pub fn download_url<S: AsRef<str>>(&self, pkg: PackageID, version: S, suffix: S) -> String {
-        format!("{repo}/{pkg}/{version}/{name}-{version}{suffix}", 
-            repo = self.url, 
-            pkg = pkg.to_path_str(), 
-            version = version.as_ref(),
+        self.artifact_url(&pkg, version.as_ref(), version.as_ref(), suffix.as_ref())
+    }
+
+    /**
+     * Url of an artifact whose file name carries `file_version`, which differs from `version`
+     * for timestamped snapshot builds.
+     */
+    pub fn artifact_url(&self, pkg: &PackageID, version: &str, file_version: &str, suffix: &str) -> String {
+        format!("{repo}/{pkg}/{version}/{name}-{file_version}{suffix}",
+            repo = self.url,
+            pkg = pkg.to_path_str(),
+            version = version,
             name = pkg.name,
-            suffix = suffix.as_ref())
+            file_version = file_version,
+            suffix = suffix)
+    }
+
+    /**
+     * Url of `maven-metadata.xml` of `pkg`, or of one snapshot `version` of it.
+     */
+    pub fn metadata_url(&self, pkg: &PackageID, version: Option<&str>) -> String {
+        match version {
+            Some(version) => format!("{}/{}/{}/maven-metadata.xml", self.url, pkg.to_path_str(), version),
+            None => format!("{}/{}/maven-metadata.xml", self.url, pkg.to_path_str())
+        }
+    }
+}
+
+impl std::fmt::Display for SourceRepo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.name.is_empty() {
+            write!(f, "{}", self.url)
+        } else {
+            write!(f, "{} ({})", self.name, self.url)
+        }
+    }
+}
+
+/**
+ * Repositories that serve `pid`, in the configured order.
+ */
+pub fn route(repos: &[SourceRepo], pid: &PackageID) -> Vec<SourceRepo> {
+    repos.iter().filter(|repo| repo.serves(pid)).cloned().collect()
+}
+
+/// Metadata of an interrupted download, stored next to its `.part` file.
+#[derive(Debug, Serialize, Deserialize)]
+struct PartMeta {
+    url: String,
+
+    /// Strong `ETag` or `Last-Modified` of the response, sent back as `If-Range` when resuming.
+    validator: String
+}
+
+impl PartMeta {
+    fn load(path: &Path) -> Option<PartMeta> {
+        serde_json::from_slice(&std::fs::read(path).ok()?).ok()
+    }
+
+    fn from_response(url: &str, resp: &Response<Body>) -> Option<PartMeta> {
+        let headers = resp.headers();
+        let etag = headers.get(ETAG).and_then(|etag| etag.to_str().ok()).filter(|etag| !etag.starts_with("W/"));
+        let validator = etag.or_else(|| headers.get(LAST_MODIFIED)?.to_str().ok())?;
+
+        Some(PartMeta { url: url.to_string(), validator: validator.to_string() })
+    }
+}
+
+/**
+ * Start offset of a `Content-Range: bytes <start>-<end>/<size>` header.
+ */
+fn content_range_start(resp: &Response<Body>) -> Option<u64> {
+    let range = resp.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let range = range.strip_prefix("bytes ")?;
+
+    range[..range.find('-')?].trim().parse().ok()
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+/// Where the sidecar files of an artifact, such as `.sha1` and `.asc`, are read from.
+enum Sidecars<'a> {
+    Remote(&'a HttpClient, &'a HeaderMap),
+    Local
+}
+
+impl Sidecars<'_> {
+    async fn read(&self, location: &str) -> Result<Option<String>> {
+        match self {
+            Sidecars::Remote(client, auth) => fetch_text(client, auth, location).await,
+            Sidecars::Local => Ok(std::fs::read_to_string(location).ok())
+        }
     }
 }
 
+/// Expected size of an artifact before it is downloaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Estimate {
+    /// Restored from the cache, which does not grow.
+    Cached(u64),
+    Download(u64),
+    Unknown
+}
+
 #[derive(Debug)]
 pub struct Downloader {
-    repo: SourceRepo,
+    repos: Vec<SourceRepo>,
     pkg_path: PathBuf,
+    insecure: bool,
+    keyring: Option<Keyring>,
+    policy: SignaturePolicy,
+    tls: TlsConfig,
+    proxies: ProxySettings,
+    network: NetworkConfig,
+    retry: RetryPolicy,
+    credentials: CredentialStore,
+    cache: Option<Cache>,
+    limiter: HostLimiter,
+    rate: RateLimiter,
+    observer: Arc<dyn DownloadObserver>,
 }
 
 impl Downloader {
-    pub fn get_download_url(&self, pkg: PackageID, ext: &'static str) -> String {
-        format!("{}/{}.{}", self.repo.url, pkg.to_path_str(), ext)
+    /**
+     * Download from `repos` in order, falling over to the next one when a repository fails.
+     */
+    pub fn new(repos: Vec<SourceRepo>, pkg_path: PathBuf) -> Self {
+        Downloader {
+            repos,
+            pkg_path,
+            insecure: false,
+            keyring: None,
+            policy: SignaturePolicy::default(),
+            tls: TlsConfig::default(),
+            proxies: ProxySettings::default(),
+            network: NetworkConfig::default(),
+            retry: RetryPolicy::default(),
+            credentials: CredentialStore::default(),
+            cache: None,
+            limiter: HostLimiter::default(),
+            rate: RateLimiter::default(),
+            observer: progress::observer()
+        }
+    }
+
+    /**
+     * Look up credentials of private repositories in `store`.
+     */
+    pub fn credentials(mut self, store: CredentialStore) -> Self {
+        self.credentials = store;
+        self
+    }
+
+    /**
+     * Reuse artifacts in `cache` instead of downloading them again, and keep every verified download there.
+     */
+    pub fn cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
     }
 
     /**
-     * Download package into file.
+     * Share the connection limits and the bandwidth with other downloaders running at once.
      */
-    pub async fn download<P: AsRef<Path>>(&self, pkg: PackageID, output: P) -> Result<()> {
-        let client = Client::new();
-        let url = Uri::from_str(&self.get_download_url(pkg, "jar"))?;
-        let mut resp = client.get(url).await?;
-        let mut file = File::open(output.as_ref())?;
-        let mut received = 0usize;
-        let size = resp.size_hint().exact();
+    pub fn concurrent(mut self, limiter: HostLimiter, rate: RateLimiter) -> Self {
+        self.limiter = limiter;
+        self.rate = rate;
+        self
+    }
 
-        if let Some(data) = resp.data().await {
-            let data = data?;
+    /**
+     * Report progress and messages to `observer`, which may be shared by downloaders running at once.
+     */
+    pub fn observer(mut self, observer: Arc<dyn DownloadObserver>) -> Self {
+        self.observer = observer;
+        self
+    }
 
-            received += data.len();
-            file.write_all(&data[..])?;
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
 
-            let process = if let Some(size) = size {
-                let f_size = f64::from(u32::try_from(size)?);
-                let f_received = f64::from(u32::try_from(received)?);
-                let process = f_received / f_size * 10.0;
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = tls;
+        self
+    }
 
-                process.floor() as u8
-            } else {
-                0
+    pub fn proxy(mut self, proxies: ProxySettings) -> Self {
+        self.proxies = proxies;
+        self
+    }
+
+    pub fn network(mut self, network: NetworkConfig) -> Self {
+        self.network = network;
+        self
+    }
+
+    /**
+     * Verify OpenPGP signatures of downloaded files with `keyring`, following `policy`.
+     */
+    pub fn signatures(mut self, keyring: Keyring, policy: SignaturePolicy) -> Self {
+        self.keyring = Some(keyring);
+        self.policy = policy;
+        self
+    }
+
+    /**
+     * Skip checksum and signature verification.
+     */
+    pub fn insecure(mut self, insecure: bool) -> Self {
+        self.insecure = insecure;
+        self
+    }
+
+    /**
+     * Download package into the package directory, returns the path of the downloaded file.
+     * The file only appears there after its checksums are verified against `expected` and
+     * the sidecar files published by the repository.
+     *
+     * Transient failures are retried with backoff, other failures move on to the next repository.
+     * Without a `suffix`, each repository is probed for the [`PROBED_SUFFIXES`] in order.
+     */
+    pub async fn download(&self, pkg: &PackageID, version: &str, suffix: Option<&str>, expected: &Checksums) -> Result<PathBuf> {
+        let client = self.tls.client(&self.proxies, &self.network)?;
+
+        if let Some(file) = self.restore(&client, pkg, version, suffix, expected).await? {
+            return Ok(file);
+        }
+        let mut last_err = None;
+
+        for (index, repo) in self.repos.iter().enumerate() {
+            if index > 0 {
+                self.observer.warn(&format!("Trying the next repository {}", repo));
+            }
+
+            let host = Uri::from_str(repo.url()).ok().and_then(|uri| uri.host().map(String::from));
+
+            for attempt in 1..=self.retry.attempts.max(1) {
+                let permit = match &host {
+                    Some(host) => Some(self.limiter.acquire(host).await),
+                    None => None
+                };
+
+                let downloaded = client.attempt(&format!("download of {} {}", pkg, version),
+                    self.download_from(&client, repo, pkg, version, suffix, expected)).await;
+                drop(permit);
+
+                let err = match downloaded {
+                    Ok(file) => {
+                        self.store(pkg, version, &file, repo);
+                        return Ok(file);
+                    }
+
+                    Err(err) => err
+                };
+
+                if !is_transient(&err) {
+                    self.observer.warn(&format!("Failed to download {} {} from {}: {}", pkg, version, repo, err));
+                    last_err = Some(err);
+                    break;
+                }
+
+                if attempt < self.retry.attempts {
+                    let delay = self.retry.delay(attempt);
+
+                    self.observer.warn(&format!("Attempt {}/{} from {} failed: {}, retrying in {:.1}s", attempt, self.retry.attempts, repo, err, delay.as_secs_f64()));
+                    tokio::time::sleep(delay).await;
+                } else {
+                    self.observer.warn(&format!("Attempt {}/{} from {} failed: {}", attempt, self.retry.attempts, repo, err));
+                }
+
+                last_err = Some(err);
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| install_err(format!("no repository is configured to download {}", pkg)).into()))
+    }
+
+    /**
+     * The cache for artifacts of `version`. Snapshots are not cached, as a newer build may be published
+     * under the same version at any time.
+     */
+    fn cache_for(&self, version: &str) -> Option<&Cache> {
+        self.cache.as_ref().filter(|_| !version.ends_with("-SNAPSHOT"))
+    }
+
+    /**
+     * Copy `pkg` from the cache into the package directory, if it is cached, matches `expected`
+     * and passes the signature check of a fresh download.
+     */
+    async fn restore(&self, client: &HttpClient, pkg: &PackageID, version: &str, suffix: Option<&str>, expected: &Checksums) -> Result<Option<PathBuf>> {
+        let cache = match self.cache_for(version) {
+            Some(cache) => cache,
+            None => return Ok(None)
+        };
+
+        std::fs::create_dir_all(&self.pkg_path)?;
+
+        let suffixes = match suffix {
+            Some(suffix) => vec![suffix],
+            None => PROBED_SUFFIXES.to_vec()
+        };
+
+        for suffix in suffixes {
+            let name = format!("{}-{}{}", pkg.name, version, suffix);
+            let part = self.pkg_path.join(format!("{}.part", name));
+
+            let (actual, source) = match cache.restore(&Cache::key(pkg, version, suffix), &part)? {
+                Some(restored) => restored,
+                None => continue
             };
 
-            TerminalDownloadObserver::update(process, (received, usize::try_from(size.unwrap_or(0))?));
+            let verified = match checksum::verify(&name, "index", expected, &actual) {
+                Ok(_) if !self.insecure => self.verify_cached(client, pkg, version, suffix, source.as_deref(), &part).await,
+                Ok(_) => Ok(()),
+                Err(err) => Err(err.into())
+            };
+
+            if let Err(err) = verified {
+                self.observer.warn(&format!("Cached {} is not used: {}", name, err));
+                std::fs::remove_file(&part)?;
+                continue;
+            }
+
+            let output = self.pkg_path.join(&name);
+            std::fs::rename(&part, &output)?;
+
+            self.observer.info(&format!("Using cached {}", name));
+
+            return Ok(Some(output));
         }
 
-        Ok(())
+        Ok(None)
     }
-}
 
-pub trait DownloadObserver {
-    fn ready();
-    fn update(process: u8, size: (usize, usize));
-}
+    /**
+     * Expected size of an artifact, from the cache, a local repository or the `Content-Length` of the first
+     * repository that has it. Failures only leave the size unknown, the download reports them.
+     */
+    pub async fn estimate(&self, pkg: &PackageID, version: &str, suffix: Option<&str>) -> Estimate {
+        let suffixes = match suffix {
+            Some(suffix) => vec![suffix],
+            None => PROBED_SUFFIXES.to_vec()
+        };
+
+        if let Some(size) = self.cache_for(version).and_then(|cache| suffixes.iter().find_map(|suffix| cache.size(&Cache::key(pkg, version, suffix)))) {
+            return Estimate::Cached(size);
+        }
+
+        let client = match self.tls.client(&self.proxies, &self.network) {
+            Ok(client) => client,
+            Err(_) => return Estimate::Unknown
+        };
+
+        for repo in self.repos.iter() {
+            let host = Uri::from_str(repo.url()).ok().and_then(|uri| uri.host().map(String::from));
+            let permit = match &host {
+                Some(host) => Some(self.limiter.acquire(host).await),
+                None => None
+            };
+
+            let size = self.size_from(&client, repo, pkg, version, &suffixes).await;
+            drop(permit);
+
+            if let Ok(Some(size)) = size {
+                return Estimate::Download(size);
+            }
+        }
 
-pub struct TerminalDownloadObserver;
+        Estimate::Unknown
+    }
+
+    async fn size_from(&self, client: &HttpClient, repo: &SourceRepo, pkg: &PackageID, version: &str, suffixes: &[&str]) -> Result<Option<u64>> {
+        let file_name = |suffix: &str| format!("{}-{}{}", pkg.name, version, suffix);
 
-impl TerminalDownloadObserver {
-    const PROCESS_MAX: u8 = 20;
+        if let Some(dir) = repo.local_dir() {
+            let dir = dir.join(pkg.to_path_str()).join(version);
 
-    pub fn make_download_str(process: u8, size: (usize, usize)) -> String {
-        if process > Self::PROCESS_MAX {
-            panic!("WHAT ARE YOU FUCKING DOING??");
+            return Ok(suffixes.iter().find_map(|suffix| std::fs::metadata(dir.join(file_name(suffix))).ok()).map(|stat| stat.len()));
         }
 
-        let processed_str = String::from_iter(vec!['>'; usize::from(process)]);
-        let unprocessed_str = String::from_iter(vec!['<'; usize::from(Self::PROCESS_MAX - process)]);
+        self.tls.check(&Uri::from_str(repo.url())?)?;
+
+        let auth = repo.headers(&self.credentials)?;
+        let snapshot = snapshot_metadata(client, &auth, repo, pkg, version).await?;
+
+        for suffix in suffixes {
+            let url = match snapshot.as_ref().and_then(|metadata| metadata.snapshot_file_version(version, suffix)) {
+                Some(file_version) => repo.artifact_url(pkg, version, &file_version, suffix),
+                None => repo.download_url(pkg.clone(), version, suffix)
+            };
+
+            let (_, resp) = client.head(Uri::from_str(&url)?, auth.clone()).await?;
+
+            if resp.status().is_success() {
+                return Ok(resp.headers().get(CONTENT_LENGTH).and_then(|length| length.to_str().ok()).and_then(|length| length.parse().ok()));
+            }
+        }
 
-        format!(
-            "{}/{} [{}{}]",
-            size.0, size.1, processed_str, unprocessed_str
-        )
+        Ok(None)
     }
-}
 
-impl DownloadObserver for TerminalDownloadObserver {
-    fn update(process: u8, size: (usize, usize)) {
-        Terminal::recover_cursor();
-        Terminal::erase_line();
+    /**
+     * The repository at `source` that a cached artifact of `pkg` was downloaded from, if it still serves `pkg`.
+     */
+    fn source_repo(&self, pkg: &PackageID, source: Option<&str>) -> Result<&SourceRepo> {
+        self.repos.iter()
+            .filter(|repo| repo.serves(pkg))
+            .find(|repo| source == Some(repo.url().as_str()))
+            .ok_or_else(|| install_err(format!("it was downloaded from {}, which no longer serves {}", source.unwrap_or("an unknown repository"), pkg)).into())
+    }
+
+    /**
+     * Check the signature of the cached artifact `part` against the one published by `source`, the repository
+     * it was downloaded from, as the cache may have been filled under a more lenient signature policy.
+     */
+    async fn verify_cached(&self, client: &HttpClient, pkg: &PackageID, version: &str, suffix: &str, source: Option<&str>, part: &Path) -> Result<()> {
+        let repo = self.source_repo(pkg, source)?;
+
+        match repo.local_dir() {
+            Some(dir) => {
+                let location = dir.join(pkg.to_path_str()).join(version).join(format!("{}-{}{}", pkg.name, version, suffix));
+
+                self.verify_signature(&Sidecars::Local, pkg, &location.to_string_lossy(), part).await
+            }
+
+            None => {
+                self.tls.check(&Uri::from_str(repo.url())?)?;
+
+                let auth = repo.headers(&self.credentials)?;
+
+                self.verify_signature(&Sidecars::Remote(client, &auth), pkg, &repo.download_url(pkg.clone(), version, suffix), part).await
+            }
+        }
+    }
+
+    /**
+     * Keep a verified download in the cache. A failure only costs a download later, so it is not fatal.
+     */
+    fn store(&self, pkg: &PackageID, version: &str, file: &Path, repo: &SourceRepo) {
+        let cache = match self.cache_for(version) {
+            Some(cache) if !self.insecure => cache,
+            _ => return
+        };
 
-        print!("{}", Self::make_download_str(process, size));
+        let prefix = format!("{}-{}", pkg.name, version);
+        let suffix = file.file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(&prefix));
+
+        if let Some(suffix) = suffix {
+            if let Err(err) = cache.store(&Cache::key(pkg, version, suffix), file, repo.url()) {
+                self.observer.warn(&format!("Warning: failed to cache {}: {}", file.display(), err));
+            }
+        }
     }
 
-    fn ready() {
-        Terminal::save_cursor();
+    /**
+     * `maven-metadata.xml` of `pkg` from the first repository that publishes it.
+     */
+    pub async fn metadata(&self, pkg: &PackageID) -> Result<Option<(SourceRepo, Metadata)>> {
+        let client = self.tls.client(&self.proxies, &self.network)?;
+
+        for repo in self.repos.iter() {
+            let xml = match repo.local_dir() {
+                Some(dir) => {
+                    let dir = dir.join(pkg.to_path_str());
+
+                    ["maven-metadata-local.xml", "maven-metadata.xml"]
+                        .iter()
+                        .find_map(|name| std::fs::read_to_string(dir.join(name)).ok())
+                }
+
+                None => {
+                    let fetched = match self.tls.check(&Uri::from_str(repo.url())?) {
+                        Ok(()) => fetch_text(&client, &repo.headers(&self.credentials)?, &repo.metadata_url(pkg, None)).await,
+                        Err(err) => Err(err)
+                    };
+
+                    match fetched {
+                        Ok(xml) => xml,
+                        Err(err) => {
+                            self.observer.warn(&format!("Failed to fetch metadata of {} from {}: {}", pkg, repo, err));
+                            None
+                        }
+                    }
+                }
+            };
+
+            if let Some(xml) = xml {
+                return Ok(Some((repo.clone(), Metadata::parse(&xml)?)));
+            }
+        }
+
+        Ok(None)
     }
-}
 
-pub struct Terminal;
+    /**
+     * The POM of `pkg` from the first repository that publishes it, checked against its sidecars like artifacts.
+     */
+    pub async fn pom(&self, pkg: &PackageID, version: &str) -> Result<Option<String>> {
+        let client = self.tls.client(&self.proxies, &self.network)?;
 
-impl Terminal {
-    pub fn save_cursor() {
-        write!(stdout(), "\x1B[s").expect("io error");
-        stdout().flush().expect("io error");
+        for repo in self.repos.iter() {
+            match client.attempt(&format!("POM of {} {}", pkg, version), self.pom_from(&client, repo, pkg, version)).await {
+                Ok(Some(pom)) => return Ok(Some(pom)),
+                Ok(None) => {}
+                Err(err) if is_transient(&err) => return Err(err),
+                Err(err) => self.observer.warn(&format!("Failed to fetch the POM of {} {} from {}: {}", pkg, version, repo, err))
+            }
+        }
+
+        Ok(None)
     }
 
-    pub fn recover_cursor() {
-        write!(stdout(), "\x1B[u").expect("io error");
-        stdout().flush().expect("io error");
+    async fn pom_from(&self, client: &HttpClient, repo: &SourceRepo, pkg: &PackageID, version: &str) -> Result<Option<String>> {
+        let name = format!("{}-{}.pom", pkg.name, version);
+
+        if let Some(dir) = repo.local_dir() {
+            return Ok(std::fs::read_to_string(dir.join(pkg.to_path_str()).join(version).join(name)).ok());
+        }
+
+        self.tls.check(&Uri::from_str(repo.url())?)?;
+
+        let auth = repo.headers(&self.credentials)?;
+        let url = match snapshot_metadata(client, &auth, repo, pkg, version).await?.and_then(|metadata| metadata.snapshot_file_version(version, ".pom")) {
+            Some(file_version) => repo.artifact_url(pkg, version, &file_version, ".pom"),
+            None => repo.download_url(pkg.clone(), version, ".pom")
+        };
+
+        let pom = match fetch_text(client, &auth, &url).await? {
+            Some(pom) => pom,
+            None => return Ok(None)
+        };
+
+        if !self.insecure {
+            let mut hasher = Hasher::default();
+            hasher.update(pom.as_bytes());
+
+            self.verify(&Sidecars::Remote(client, &auth), &url, &name, &Checksums::new(), &hasher.finish()).await?;
+        }
+
+        Ok(Some(pom))
     }
 
-    pub fn erase_line() {
-        write!(stdout(), "\x1B[2K").expect("io error");
-        stdout().flush().expect("io error");
+    async fn download_from(&self, client: &HttpClient, repo: &SourceRepo, pkg: &PackageID, version: &str, suffix: Option<&str>, expected: &Checksums) -> Result<PathBuf> {
+        std::fs::create_dir_all(&self.pkg_path)?;
+
+        if let Some(dir) = repo.local_dir() {
+            let dir = dir.join(pkg.to_path_str()).join(version);
+            let file_name = |suffix: &str| format!("{}-{}{}", pkg.name, version, suffix);
+
+            let suffix = match suffix {
+                Some(suffix) => suffix,
+                None => PROBED_SUFFIXES.iter()
+                    .copied()
+                    .find(|suffix| dir.join(file_name(suffix)).exists())
+                    .ok_or_else(|| install_err(format!("no artifact of {} {} found in local repository {}, tried {}", pkg, version, repo, PROBED_SUFFIXES.join(", "))))?
+            };
+
+            let name = file_name(suffix);
+            let src = dir.join(&name);
+            let part = self.pkg_path.join(format!("{}.part", name));
+
+            if !src.exists() {
+                return Err(install_err(format!("{} not found in local repository {}", name, repo)).into());
+            }
+
+            let actual = copy_hashed(&src, &part)?;
+
+            self.observer.info(&format!("Copied {}", src.display()));
+
+            return self.finish(&Sidecars::Local, pkg, &src.to_string_lossy(), &name, expected, &actual).await;
+        }
+
+        self.tls.check(&Uri::from_str(repo.url())?)?;
+
+        let auth = repo.headers(&self.credentials)?;
+
+        let snapshot = snapshot_metadata(client, &auth, repo, pkg, version).await?;
+        let file_version = |suffix: &str| snapshot.as_ref().and_then(|metadata| metadata.snapshot_file_version(version, suffix));
+        let url_of = |suffix: &str| match file_version(suffix) {
+            Some(file_version) => repo.artifact_url(pkg, version, &file_version, suffix),
+            None => repo.download_url(pkg.clone(), version, suffix)
+        };
+
+        let suffix = match suffix {
+            Some(suffix) => suffix,
+            None => probe(client, &auth, &url_of).await?
+                .ok_or_else(|| install_err(format!("no artifact of {} {} found in {}, tried {}", pkg, version, repo, PROBED_SUFFIXES.join(", "))))?
+        };
+
+        let name = format!("{}-{}{}", pkg.name, version, suffix);
+        let part = self.pkg_path.join(format!("{}.part", name));
+        let url = url_of(suffix);
+
+        if let Some(file_version) = file_version(suffix) {
+            self.observer.info(&format!("Resolved {} {} to build {}", pkg, version, file_version));
+        }
+
+        let uri = Uri::from_str(&url)?;
+
+        let mut meta_path = self.pkg_path.clone();
+        meta_path.push(format!("{}.part.json", name));
+
+        let mut resume = match (PartMeta::load(&meta_path), std::fs::metadata(&part)) {
+            (Some(meta), Ok(stat)) if meta.url == url && stat.len() > 0 => Some((meta.validator, stat.len())),
+            _ => None
+        };
+
+        let (final_uri, mut resp, offset) = loop {
+            let mut headers = auth.clone();
+
+            if let Some((validator, offset)) = &resume {
+                headers.insert(RANGE, format!("bytes={}-", offset).parse()?);
+                headers.insert(IF_RANGE, validator.parse()?);
+            }
+
+            let (final_uri, resp) = client.send(uri.clone(), headers).await?;
+
+            match resume {
+                Some((_, offset)) if resp.status() == StatusCode::PARTIAL_CONTENT && content_range_start(&resp) == Some(offset) => {
+                    self.observer.info(&format!("Resuming {} from {} bytes.", name, offset));
+                    break (final_uri, resp, offset);
+                }
+
+                Some(_) if resp.status() != StatusCode::OK => {
+                    // the range cannot be served, ask for the whole file again
+                    resume = None;
+                    continue;
+                }
+
+                Some(_) => {
+                    self.observer.info(&format!("The server does not resume {}, starting over.", name));
+                    break (final_uri, resp, 0);
+                }
+
+                None => break (final_uri, resp, 0)
+            }
+        };
+
+        if final_uri.to_string() != url {
+            self.observer.info(&format!("Redirected to {}", final_uri));
+        }
+
+        if resp.status().is_server_error() || resp.status() == StatusCode::TOO_MANY_REQUESTS {
+            return Err(transfer_err(format!("failed to download {}: {}", url, resp.status())).into());
+        }
+
+        if !resp.status().is_success() {
+            return Err(install_err(format!("failed to download {}: {}", url, resp.status())).into());
+        }
+
+        let mut hasher = Hasher::default();
+
+        let mut file = if offset > 0 {
+            let mut file = OpenOptions::new().read(true).append(true).open(&part)?;
+            let mut buffer = vec![0; 64 * 1024];
+
+            loop {
+                let read = file.read(&mut buffer)?;
+
+                if read == 0 {
+                    break file;
+                }
+
+                hasher.update(&buffer[..read]);
+            }
+        } else {
+            File::create(&part)?
+        };
+
+        match PartMeta::from_response(&url, &resp) {
+            Some(meta) => std::fs::write(&meta_path, serde_json::to_string(&meta)?)?,
+            None => remove_if_exists(&meta_path)?
+        }
+
+        let mut received = offset;
+        let size = resp.size_hint().exact().map(|size| size + offset);
+        let progress = Progress::start(&self.observer, &name, received, size);
+
+        let transferred: Result<()> = async {
+            while let Some(data) = client.chunk(&url, &mut resp).await? {
+                received += data.len() as u64;
+                file.write_all(&data[..])?;
+                hasher.update(&data[..]);
+
+                progress.update(received);
+                self.rate.consume(data.len()).await;
+            }
+
+            Ok(())
+        }.await;
+
+        progress.finish(transferred)?;
+        drop(file);
+        remove_if_exists(&meta_path)?;
+
+        self.finish(&Sidecars::Remote(client, &auth), pkg, &url, &name, expected, &hasher.finish()).await
+    }
+
+    /**
+     * Verify the downloaded `<name>.part` fetched from `location`, then move it into place.
+     */
+    async fn finish(&self, sidecars: &Sidecars<'_>, pkg: &PackageID, location: &str, name: &str, expected: &Checksums, actual: &Checksums) -> Result<PathBuf> {
+        let output = self.pkg_path.join(name);
+        let part = self.pkg_path.join(format!("{}.part", name));
+
+        if !self.insecure {
+            self.observer.phase(&Phase::Verify { name });
+
+            let verified = match self.verify(sidecars, location, name, expected, actual).await {
+                Ok(()) => self.verify_signature(sidecars, pkg, location, &part).await,
+                err => err
+            };
+
+            if let Err(err) = verified {
+                std::fs::remove_file(&part)?;
+                return Err(err);
+            }
+        }
+
+        std::fs::rename(&part, &output)?;
+
+        Ok(output)
+    }
+
+    /**
+     * Verify `actual` digests against the index and every sidecar the repository publishes.
+     * At least one of them must be available for a remote repository, while builds in a local
+     * repository seldom come with checksums.
+     */
+    async fn verify(&self, sidecars: &Sidecars<'_>, url: &str, name: &str, expected: &Checksums, actual: &Checksums) -> Result<()> {
+        let mut checked = checksum::verify(name, "index", expected, actual)?;
+
+        for algo in Algorithm::ALL.iter() {
+            let sidecar = format!("{}{}", url, algo.extension());
+
+            if let Some(digest) = sidecars.read(&sidecar).await?.as_deref().and_then(checksum::parse_sidecar) {
+                let mut published = Checksums::new();
+                published.insert(*algo, digest);
+
+                checked += checksum::verify(name, &sidecar, &published, actual)?;
+            }
+        }
+
+        if checked == 0 && matches!(sidecars, Sidecars::Remote(..)) {
+            return Err(checksum_err(format!("no checksum is published for {}, use --insecure to install it anyway", url)).into());
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Check the `.asc` detached signature of the downloaded `file` against the keyring.
+     */
+    async fn verify_signature(&self, sidecars: &Sidecars<'_>, pkg: &PackageID, url: &str, file: &Path) -> Result<()> {
+        let keyring = match &self.keyring {
+            Some(keyring) if self.policy.enabled() => keyring,
+            _ => return Ok(())
+        };
+
+        let signature_url = format!("{}.asc", url);
+        let signature = sidecars.read(&signature_url).await?;
+        let data = std::fs::read(file)?;
+
+        let (action, reason) = match keyring.verify(pkg, &data, signature.as_deref()) {
+            Verdict::Good { fingerprint, uid } => {
+                self.observer.info(&format!("Good signature from {} ({})", uid, fingerprint));
+                return Ok(());
+            }
+
+            Verdict::Missing => (self.policy.missing, format!("no signature is published for {}", url)),
+            Verdict::Invalid(reason) => (self.policy.invalid, format!("bad signature {}: {}", signature_url, reason))
+        };
+
+        match action {
+            Action::Ignore => Ok(()),
+            Action::Warn => {
+                self.observer.warn(&format!("Warning: {}", reason));
+                Ok(())
+            }
+            Action::Refuse => Err(signature_err(reason).into())
+        }
     }
 }
 
-#[cfg(test)]
-pub mod tests {
-    use super::*;
+/**
+ * `maven-metadata.xml` of a snapshot `version`, `None` for releases.
+ */
+async fn snapshot_metadata(client: &HttpClient, auth: &HeaderMap, repo: &SourceRepo, pkg: &PackageID, version: &str) -> Result<Option<Metadata>> {
+    if !version.ends_with("-SNAPSHOT") {
+        return Ok(None);
+    }
 
-    // #[test]
-    // pub fn test_downloading() {
-    //     println!("Downloading...");
+    fetch_text(client, auth, &repo.metadata_url(pkg, Some(version))).await?
+        .map(|xml| Metadata::parse(&xml))
+        .transpose()
+}
 
-    //     Terminal::save_cursor();
+/**
+ * First of [`PROBED_SUFFIXES`] whose artifact at `url_of(suffix)` exists, checked with HEAD requests.
+ */
+async fn probe(client: &HttpClient, auth: &HeaderMap, url_of: impl Fn(&str) -> String) -> Result<Option<&'static str>> {
+    for suffix in PROBED_SUFFIXES.iter() {
+        let (_, resp) = client.head(Uri::from_str(&url_of(suffix))?, auth.clone()).await?;
 
-    //     let job = std::thread::spawn(|| {
-    //         let mut process = 0;
+        if resp.status().is_server_error() || resp.status() == StatusCode::TOO_MANY_REQUESTS {
+            return Err(transfer_err(format!("failed to probe {}: {}", url_of(suffix), resp.status())).into());
+        }
+
+        if resp.status().is_success() {
+            return Ok(Some(*suffix));
+        }
+    }
+
+    Ok(None)
+}
 
-    //         while process < 100 {
-    //             Terminal::recover_cursor();
-    //             Terminal::erase_line();
+/**
+ * GET a small text file, `None` if the server does not have it.
+ */
+async fn fetch_text(client: &HttpClient, auth: &HeaderMap, url: &str) -> Result<Option<String>> {
+    let (_, resp) = client.send(Uri::from_str(url)?, auth.clone()).await?;
 
-    //             process += 1;
+    if !resp.status().is_success() {
+        return Ok(None);
+    }
 
-    //             print!(
-    //                 "{}",
-    //                 Downloader::make_download_str(process / 5, (usize::from(process), 100))
-    //             );
-    //             std::io::stdout().flush().unwrap();
+    let body = client.body(url, resp).await?;
 
-    //             std::thread::sleep(Duration::from_secs(1));
-    //         }
-    //     });
+    Ok(Some(String::from_utf8_lossy(&body).into_owned()))
+}
 
-    //     job.join().unwrap();
-    // }
+#[cfg(test)]
+pub mod tests {
+    use super::*;
 
     // #[tokio::test]
     // pub async fn test_fetching() {
@@ -202,6 +987,82 @@ pub mod tests {
         assert_eq!(expected, repo.download_url(pkg, "2.4.0", "-all.jar"));
     }
 
+    #[test]
+    pub fn routing() {
+        let nexus = SourceRepo {
+            name: String::from("ourteam"),
+            url: String::from("https://nexus.ourteam.com/repository/maven-releases"),
+            include: vec![String::from("com.ourteam:*")],
+            exclude: Vec::new(),
+            headers: BTreeMap::new(),
+            auth: RepoAuth::default()
+        };
+
+        let aliyun = SourceRepo {
+            exclude: vec![String::from("com.ourteam")],
+            ..SourceRepo::from_url("https://maven.aliyun.com/repository/public")
+        };
+
+        let repos = [nexus, aliyun];
+        let route = |pid: &str| route(&repos, &pid.parse().unwrap()).into_iter().map(|repo| repo.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(vec!["ourteam (https://nexus.ourteam.com/repository/maven-releases)"], route("com.ourteam:bot"));
+        assert_eq!(vec!["https://maven.aliyun.com/repository/public"], route("net.mamoe:chat-command"));
+    }
+
+    #[test]
+    pub fn repo_headers() {
+        let repo: SourceRepo = toml::from_str(r#"
+            name = "ourteam"
+            url = "https://gitlab.ourteam.com/api/v4/packages/maven"
+            headers = { "Private-Token" = "glpat-secret" }
+        "#).unwrap();
+
+        let headers = repo.headers(&CredentialStore::default()).unwrap();
+        assert_eq!("glpat-secret", headers["private-token"]);
+        assert!(headers["private-token"].is_sensitive());
+        assert!(!toml::to_string(&repo).unwrap().contains("glpat-secret"));
+
+        let invalid = SourceRepo { headers: vec![(String::from("Private Token"), Secret::new("x"))].into_iter().collect(), ..repo };
+        assert!(invalid.headers(&CredentialStore::default()).is_err());
+    }
+
+    #[test]
+    pub fn snapshots_not_cached() {
+        let downloader = Downloader::new(Vec::new(), PathBuf::new()).cache(Cache::new(PathBuf::from("/tmp/mpt-get-cache"), Default::default()));
+
+        assert!(downloader.cache_for("2.5.0").is_some());
+        assert!(downloader.cache_for("2.5.0-SNAPSHOT").is_none());
+    }
+
+    #[test]
+    pub fn cached_source() {
+        let nexus = SourceRepo { include: vec![String::from("com.ourteam:*")], ..SourceRepo::from_url("https://nexus.ourteam.com/repository/maven-releases") };
+        let aliyun = SourceRepo { exclude: vec![String::from("com.ourteam:*")], ..SourceRepo::from_url("https://maven.aliyun.com/repository/public") };
+        let downloader = Downloader::new(vec![aliyun, nexus], PathBuf::new());
+        let bot: PackageID = "com.ourteam:bot".parse().unwrap();
+
+        assert_eq!("https://nexus.ourteam.com/repository/maven-releases", downloader.source_repo(&bot, Some("https://nexus.ourteam.com/repository/maven-releases")).unwrap().url());
+        assert!(downloader.source_repo(&bot, Some("https://maven.aliyun.com/repository/public")).is_err());
+        assert!(downloader.source_repo(&bot, None).is_err());
+    }
+
+    #[test]
+    pub fn local_repo() {
+        assert_eq!(Some(PathBuf::from("/srv/maven")), SourceRepo::from_url("file:///srv/maven").local_dir());
+        assert_eq!(Some(PathBuf::from("/srv/maven")), SourceRepo::from_url("/srv/maven").local_dir());
+        assert!(SourceRepo::from_url("~/.m2/repository").local_dir().unwrap().ends_with(".m2/repository"));
+        assert_eq!(None, SourceRepo::from_url("https://maven.aliyun.com/repository/public").local_dir());
+    }
+
+    #[test]
+    pub fn resume_offset() {
+        let resp = |range: &str| Response::builder().header(CONTENT_RANGE, range).body(Body::empty()).unwrap();
+
+        assert_eq!(Some(333333), content_range_start(&resp("bytes 333333-999999/1000000")));
+        assert_eq!(None, content_range_start(&resp("bytes */1000000")));
+    }
+
     #[test]
     pub fn do_download() {
         
@@ -0,0 +1,298 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use serde::Serialize;
+
+use crate::index::{PackageID, PackageVersion};
+use crate::resolve::Resolution;
+use crate::state::InstalledState;
+use crate::version::VersionReq;
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct GraphNode {
+    pub id: PackageID,
+
+    /// `None` when a dependency is neither selected nor available.
+    pub version: Option<String>,
+    pub channel: Option<String>,
+    pub explicit: bool,
+    pub held: bool,
+    pub pin: Option<VersionReq>
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct GraphEdge {
+    pub from: PackageID,
+    pub to: PackageID,
+    pub req: VersionReq,
+
+    /// The selected version of `to` does not satisfy `req`, or `to` is missing.
+    pub conflict: bool
+}
+
+/// Dependency graph between selected packages.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Graph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>
+}
+
+/// A version picked for a package, and whether it was requested explicitly.
+pub type Selection = BTreeMap<PackageID, (String, String, bool)>;
+
+pub fn selection_of_state(state: &InstalledState) -> Selection {
+    state.packages
+        .iter()
+        .map(|(pid, pkg)| (pid.clone(), (pkg.version.clone(), pkg.channel.clone(), !pkg.auto)))
+        .collect()
+}
+
+pub fn selection_of_resolution(resolution: &Resolution) -> Selection {
+    resolution
+        .iter()
+        .map(|(pid, resolved)| (pid.clone(), (resolved.version.clone(), resolved.channel.clone(), resolved.explicit)))
+        .collect()
+}
+
+/**
+ * Add `roots` at their newest version to `selection`, together with the newest matching version of
+ * every dependency not selected yet. Used to draw what does not resolve.
+ */
+pub fn select_newest(versions: &HashMap<PackageID, PackageVersion>, selection: &mut Selection, roots: &[PackageID]) {
+    let mut queue: Vec<(PackageID, Option<VersionReq>, bool)> = roots.iter().map(|pid| (pid.clone(), None, true)).collect();
+
+    while let Some((pid, req, explicit)) = queue.pop() {
+        if selection.contains_key(&pid) && !(explicit && roots.contains(&pid)) {
+            continue;
+        }
+
+        let vers = match versions.get(&pid) {
+            Some(vers) => vers,
+            None => continue
+        };
+
+        let newest = vers
+            .newest(|v| req.as_ref().map(|req| req.matches_str(v)).unwrap_or(true))
+            .or_else(|| vers.newest(|_| true));
+
+        if let Some((channel, version)) = newest {
+            // a root already at its newest version still brings in its dependencies
+            if !selection.get(&pid).map(|(v, _, _)| v == version).unwrap_or(false) {
+                selection.insert(pid.clone(), (version.to_string(), channel.to_string(), explicit));
+            }
+
+            for (dep, req) in vers.dependencies_of(version) {
+                queue.push((dep, Some(req), false));
+            }
+        }
+    }
+}
+
+impl Graph {
+    /**
+     * Build the graph of `selection`, keeping only what is reachable from `roots` if any is given.
+     */
+    pub fn build(versions: &HashMap<PackageID, PackageVersion>, state: &InstalledState, selection: &Selection, roots: &[PackageID]) -> Graph {
+        let mut graph = Graph::default();
+        let mut missing = BTreeSet::new();
+        let reachable = reachable(versions, selection, roots);
+
+        for (pid, (version, channel, explicit)) in selection.iter().filter(|(pid, _)| reachable.contains(*pid)) {
+            graph.nodes.push(GraphNode {
+                id: pid.clone(),
+                version: Some(version.clone()),
+                channel: Some(channel.clone()),
+                explicit: *explicit,
+                held: state.is_held(pid),
+                pin: state.pin(pid).cloned()
+            });
+
+            let deps = versions.get(pid).map(|vers| vers.dependencies_of(version)).unwrap_or_default();
+
+            for (dep, req) in deps {
+                let conflict = match selection.get(&dep) {
+                    Some((dep_version, _, _)) => !req.matches_str(dep_version),
+                    None => {
+                        missing.insert(dep.clone());
+                        true
+                    }
+                };
+
+                graph.edges.push(GraphEdge { from: pid.clone(), to: dep, req, conflict });
+            }
+        }
+
+        for pid in missing {
+            graph.nodes.push(GraphNode {
+                held: state.is_held(&pid),
+                pin: state.pin(&pid).cloned(),
+                id: pid,
+                version: None,
+                channel: None,
+                explicit: false
+            });
+        }
+
+        graph
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("graph is always serializable")
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph mpt {\n    node [shape=box];\n");
+
+        for node in self.nodes.iter() {
+            let mut label = node.id.to_string();
+            let mut attrs = Vec::new();
+            let mut styles = Vec::new();
+
+            match (&node.version, &node.channel) {
+                (Some(version), Some(channel)) => label.push_str(&format!("\\n{} [{}]", version, channel)),
+                _ => {
+                    label.push_str("\\n[missing]");
+                    styles.push("dashed");
+                    attrs.push(String::from("color=red"));
+                }
+            }
+
+            if let Some(pin) = &node.pin {
+                label.push_str(&format!("\\npinned {}", pin));
+            }
+
+            if node.held {
+                label.push_str("\\nheld");
+                styles.push("filled");
+                attrs.push(String::from("fillcolor=lightblue"));
+            }
+
+            if !styles.is_empty() {
+                attrs.insert(0, format!("style=\"{}\"", styles.join(",")));
+            }
+
+            if node.explicit {
+                attrs.push(String::from("penwidth=2"));
+            }
+
+            attrs.insert(0, format!("label=\"{}\"", escape(&label)));
+            out.push_str(&format!("    \"{}\" [{}];\n", node.id, attrs.join(", ")));
+        }
+
+        for edge in self.edges.iter() {
+            let color = if edge.conflict { ", color=red, fontcolor=red" } else { "" };
+
+            out.push_str(&format!("    \"{}\" -> \"{}\" [label=\"{}\"{}];\n", edge.from, edge.to, escape(&edge.req.to_string()), color));
+        }
+
+        out.push_str("}\n");
+        out
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('"', "\\\"")
+}
+
+fn reachable(versions: &HashMap<PackageID, PackageVersion>, selection: &Selection, roots: &[PackageID]) -> BTreeSet<PackageID> {
+    if roots.is_empty() {
+        return selection.keys().cloned().collect();
+    }
+
+    let mut seen = BTreeSet::new();
+    let mut queue: Vec<PackageID> = roots.to_vec();
+
+    while let Some(pid) = queue.pop() {
+        if !seen.insert(pid.clone()) {
+            continue;
+        }
+
+        if let (Some(vers), Some((version, _, _))) = (versions.get(&pid), selection.get(&pid)) {
+            queue.extend(vers.dependencies_of(version).into_keys());
+        }
+    }
+
+    seen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pid(s: &str) -> PackageID {
+        s.parse().unwrap()
+    }
+
+    fn versions() -> HashMap<PackageID, PackageVersion> {
+        let json = |s: &str| PackageVersion::from_value(serde_json::from_str(s).unwrap()).unwrap();
+        let mut versions = HashMap::new();
+
+        versions.insert(pid("net.mamoe:mirai-console"), json(r#"{"channels": {"stable": ["1.0", "2.4.0"]}}"#));
+        versions.insert(pid("net.mamoe:chat-command"), json(r#"{
+            "channels": {"stable": ["0.4.0"]},
+            "dependencies": {"0.4.0": {"net.mamoe:mirai-console": ">=2.0"}}
+        }"#));
+
+        versions
+    }
+
+    #[test]
+    fn conflict_edge() {
+        let versions = versions();
+        let mut state = InstalledState::default();
+        let mut selection = Selection::new();
+
+        state.holds.insert(pid("net.mamoe:mirai-console"));
+        selection.insert(pid("net.mamoe:mirai-console"), ("1.0".to_string(), "stable".to_string(), true));
+        selection.insert(pid("net.mamoe:chat-command"), ("0.4.0".to_string(), "stable".to_string(), true));
+
+        let graph = Graph::build(&versions, &state, &selection, &[]);
+
+        assert_eq!(1, graph.edges.len());
+        assert!(graph.edges[0].conflict);
+        assert!(graph.nodes.iter().any(|node| node.held));
+
+        let dot = graph.to_dot();
+
+        assert!(dot.contains("\"net.mamoe:chat-command\" -> \"net.mamoe:mirai-console\" [label=\">=2.0\", color=red, fontcolor=red];"), "{}", dot);
+    }
+
+    #[test]
+    fn newest_selection() {
+        let versions = versions();
+        let mut selection = Selection::new();
+
+        select_newest(&versions, &mut selection, &[pid("net.mamoe:chat-command")]);
+
+        let graph = Graph::build(&versions, &InstalledState::default(), &selection, &[pid("net.mamoe:chat-command")]);
+        let json: serde_json::Value = serde_json::from_str(&graph.to_json()).unwrap();
+
+        assert_eq!("2.4.0", selection[&pid("net.mamoe:mirai-console")].0);
+        assert_eq!(false, json["edges"][0]["conflict"]);
+        assert_eq!(2, json["nodes"].as_array().unwrap().len());
+    }
+
+    #[test]
+    fn selected_root_dependencies() {
+        let versions = versions();
+        let mut selection = Selection::new();
+        selection.insert(pid("net.mamoe:chat-command"), ("0.4.0".to_string(), "stable".to_string(), true));
+
+        select_newest(&versions, &mut selection, &[pid("net.mamoe:chat-command")]);
+
+        assert_eq!("2.4.0", selection[&pid("net.mamoe:mirai-console")].0);
+    }
+
+    #[test]
+    fn held_and_missing() {
+        let versions = versions();
+        let mut state = InstalledState::default();
+        let mut selection = Selection::new();
+
+        state.holds.insert(pid("net.mamoe:mirai-console"));
+        selection.insert(pid("net.mamoe:chat-command"), ("0.4.0".to_string(), "stable".to_string(), true));
+
+        let dot = Graph::build(&versions, &state, &selection, &[]).to_dot();
+
+        assert!(dot.contains("[label=\"net.mamoe:mirai-console\\n[missing]\\nheld\", style=\"dashed,filled\", color=red, fillcolor=lightblue];"), "{}", dot);
+    }
+}
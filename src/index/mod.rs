@@ -1,5 +1,7 @@
 mod package;
 mod update;
+mod verify;
 
 pub use update::*;
-pub use package::*;
\ No newline at end of file
+pub use package::*;
+pub use verify::*;
\ No newline at end of file
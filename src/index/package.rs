@@ -1,11 +1,13 @@
 use regex::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use anyhow::Result;
 
-use std::{collections::HashMap, convert::TryFrom, fs::File, hash::Hash, io::BufReader, path::{Path, PathBuf}};
+use std::{collections::{BTreeMap, HashMap}, convert::TryFrom, fs::File, hash::Hash, io::BufReader, path::{Path, PathBuf}, str::FromStr};
 
-use crate::error::{index_err, io_err};
+use crate::error::{index_err, io_err, parse_err};
+use crate::checksum::Checksums;
+use crate::version::VersionReq;
 
 macro_rules! make_err {
     ( $file:literal , $reason:literal ) => {{
@@ -23,7 +25,7 @@ macro_rules! make_err {
     };
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct PackageID {
     pub domain: String,
     pub name: String
@@ -52,6 +54,37 @@ impl PackageID {
     }
 }
 
+/**
+ * Match `text` against `pattern`, where `*` matches any sequence of characters.
+ */
+fn glob_match(pattern: &str, text: &str) -> bool {
+    match pattern.find('*') {
+        None => pattern == text,
+        Some(idx) => {
+            let (prefix, rest) = (&pattern[..idx], &pattern[idx + 1..]);
+
+            text.starts_with(prefix) && (0..=text.len() - prefix.len())
+                .filter(|skip| text.is_char_boundary(prefix.len() + skip))
+                .any(|skip| glob_match(rest, &text[prefix.len() + skip..]))
+        }
+    }
+}
+
+impl PackageID {
+    /**
+     * Match against a pattern such as `net.mamoe:*`, `com.ourteam.*:*` or `net.mamoe:chat-command`.
+     * A pattern without `:` only matches the domain.
+     */
+    pub fn matches<S: AsRef<str>>(&self, pattern: S) -> bool {
+        let pattern = pattern.as_ref();
+
+        match pattern.find(':') {
+            Some(idx) => glob_match(&pattern[..idx], &self.domain) && glob_match(&pattern[idx + 1..], &self.name),
+            None => glob_match(pattern, &self.domain)
+        }
+    }
+}
+
 impl std::hash::Hash for PackageID {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.domain.hash(state);
@@ -65,6 +98,28 @@ impl std::fmt::Display for PackageID {
     }
 }
 
+impl FromStr for PackageID {
+    type Err = crate::error::Error;
+
+    fn from_str(str: &str) -> std::result::Result<Self, Self::Err> {
+        let reg = Regex::new(r#"^([\w\d\.\-]+):([\w\d\.\-]+)$"#).unwrap();
+        let matches = reg.captures(str).ok_or_else(|| parse_err(format!("invalid pid '{}'", str)))?;
+
+        Ok(PackageID {
+            domain: matches[1].to_string(),
+            name: matches[2].to_string()
+        })
+    }
+}
+
+impl Serialize for PackageID {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer {
+        serializer.collect_str(self)
+    }
+}
+
 impl <'de> Deserialize<'de> for PackageID {
     fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
     where
@@ -85,15 +140,7 @@ impl <'de> Deserialize<'de> for PackageID {
             }
 
             fn visit_string<E>(self, str: String) -> std::result::Result<Self::Value, E> where E: Error {
-                let reg = Regex::new(r#"^([\w\d\.\-]+):([\w\d\.\-]+)$"#).unwrap();
-                let matches = reg.captures(&str).ok_or(Error::custom("invalid pid"))?;
-
-                let pid = PackageID {
-                    domain: matches[1].to_string(),
-                    name: matches[2].to_string()
-                };
-
-                Ok(pid)
+                str.parse().map_err(|_| Error::custom("invalid pid"))
             }
         }
 
@@ -114,13 +161,59 @@ pub struct PackageEntry {
     pub website: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PackageVersion {
     pub channels: HashMap<String, Versions>,
+
+    /// Dependencies of each version, versions without an entry have no dependencies.
+    pub dependencies: HashMap<String, Dependencies>,
+
+    /// Checksums of the artifact of each version, verified in addition to the repository's ones.
+    pub checksums: HashMap<String, Checksums>,
+
+    /// Suffix of the artifact file, such as `-all.jar`, probed from the repository if not declared.
+    pub artifact: Option<String>,
+
+    /// Suffix of the artifact of versions that differ from `artifact`.
+    pub artifacts: HashMap<String, String>,
+}
+
+/// Artifact file in package.json, either a suffix such as `".mirai.jar"`
+/// or `{ "classifier": "all", "extension": "jar" }`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ArtifactSpec {
+    Suffix(String),
+    Parts {
+        classifier: Option<String>,
+        extension: Option<String>
+    }
+}
+
+impl ArtifactSpec {
+    fn into_suffix(self) -> Result<String> {
+        match self {
+            ArtifactSpec::Suffix(suffix) if suffix.starts_with('.') || suffix.starts_with('-') => Ok(suffix),
+            ArtifactSpec::Suffix(suffix) => Err(index_err(format!("failed to parse package.json: artifact suffix {:?} should start with '.' or '-'", suffix)).into()),
+            ArtifactSpec::Parts { classifier, extension } => {
+                let extension = extension.unwrap_or_else(|| String::from("jar"));
+
+                Ok(match classifier {
+                    Some(classifier) => format!("-{}.{}", classifier, extension),
+                    None => format!(".{}", extension)
+                })
+            }
+        }
+    }
 }
 
 pub type Versions = Vec<String>;
 
+pub type Dependencies = BTreeMap<PackageID, VersionReq>;
+
+/// Channels in the order they are preferred when no version is given.
+pub const CHANNEL_PRIORITY: [&str; 3] = ["stable", "nightly", "beta"];
+
 fn value_from_file<P: AsRef<Path>>(path: P) -> Result<Value> {
     let path = path.as_ref();
 
@@ -158,6 +251,16 @@ impl Packages {
         Packages::from_value(value_from_file(path)?)
     }
 
+    /**
+     * Load `package.json` of every listed package.
+     */
+    pub fn versions<P: AsRef<Path>>(&self, index_dir: P) -> Result<HashMap<PackageID, PackageVersion>> {
+        self.map
+            .keys()
+            .map(|pid| Ok((pid.clone(), PackageVersion::from_pid(pid, index_dir.as_ref())?)))
+            .collect()
+    }
+
     pub fn list(&self) -> &HashMap<PackageID, PackageEntry> {
         &self.map
     }
@@ -201,7 +304,90 @@ impl PackageVersion {
             })
             .collect();
 
-        Ok(PackageVersion { channels })
+        let dependencies = match obj.get("dependencies") {
+            Some(deps) => serde_json::from_value(deps.clone())
+                .map_err(|err| index_err(format!("failed to parse package.json: invalid \"dependencies\" field: {}", err)))?,
+            None => HashMap::new()
+        };
+
+        let checksums = match obj.get("checksums") {
+            Some(sums) => serde_json::from_value(sums.clone())
+                .map_err(|err| index_err(format!("failed to parse package.json: invalid \"checksums\" field: {}", err)))?,
+            None => HashMap::new()
+        };
+
+        let artifact = match obj.get("artifact") {
+            Some(spec) => Some(serde_json::from_value::<ArtifactSpec>(spec.clone())
+                .map_err(|err| index_err(format!("failed to parse package.json: invalid \"artifact\" field: {}", err)))?
+                .into_suffix()?),
+            None => None
+        };
+
+        let artifacts = match obj.get("artifacts") {
+            Some(specs) => serde_json::from_value::<HashMap<String, ArtifactSpec>>(specs.clone())
+                .map_err(|err| index_err(format!("failed to parse package.json: invalid \"artifacts\" field: {}", err)))?
+                .into_iter()
+                .map(|(version, spec)| Ok((version, spec.into_suffix()?)))
+                .collect::<Result<_>>()?,
+            None => HashMap::new()
+        };
+
+        Ok(PackageVersion { channels, dependencies, checksums, artifact, artifacts })
+    }
+
+    /**
+     * Declared suffix of the artifact of `version`, `None` if the repository should be probed.
+     */
+    pub fn suffix_of<S: AsRef<str>>(&self, version: S) -> Option<&str> {
+        self.artifacts.get(version.as_ref()).or(self.artifact.as_ref()).map(String::as_str)
+    }
+
+    pub fn checksums_of<S: AsRef<str>>(&self, version: S) -> Checksums {
+        self.checksums.get(version.as_ref()).cloned().unwrap_or_default()
+    }
+
+    pub fn dependencies_of<S: AsRef<str>>(&self, version: S) -> Dependencies {
+        self.dependencies.get(version.as_ref()).cloned().unwrap_or_default()
+    }
+
+    /**
+     * Every version of every channel, newest first, with `first` channel ahead of `CHANNEL_PRIORITY`.
+     */
+    pub fn candidates(&self, first: Option<&str>) -> Vec<(&str, &str)> {
+        let mut channels: Vec<&str> = first.into_iter().collect();
+        let mut rest: Vec<&str> = self.channels.keys().map(String::as_str).collect();
+
+        rest.sort_by_key(|channel| CHANNEL_PRIORITY.iter().position(|c| c == channel).unwrap_or(CHANNEL_PRIORITY.len()));
+        channels.extend(rest.into_iter().filter(|channel| Some(*channel) != first));
+
+        channels
+            .into_iter()
+            .filter_map(|channel| self.channels.get_key_value(channel))
+            .flat_map(|(channel, versions)| versions.iter().rev().map(move |v| (channel.as_str(), v.as_str())))
+            .collect()
+    }
+
+    /**
+     * Find the channel that publishes `version`.
+     */
+    pub fn channel_of<S: AsRef<str>>(&self, version: S) -> Option<&String> {
+        self.channels
+            .iter()
+            .find(|(_, versions)| versions.iter().any(|v| v == version.as_ref()))
+            .map(|(channel, _)| channel)
+    }
+
+    /**
+     * The newest version accepted by `filter`, looking at channels by `CHANNEL_PRIORITY`.
+     * Versions are listed from oldest to newest in the index.
+     */
+    pub fn newest<F: Fn(&str) -> bool>(&self, filter: F) -> Option<(&str, &str)> {
+        CHANNEL_PRIORITY.iter().find_map(|channel| {
+            let versions = self.channels.get(*channel)?;
+            let ver = versions.iter().rev().find(|v| filter(v))?;
+
+            Some((*channel, ver.as_str()))
+        })
     }
 
     pub fn from_pid<P: AsRef<Path>>(pid: &PackageID, dir: P) -> Result<PackageVersion> {
@@ -237,6 +423,11 @@ mod tests {
                 "1.9.7",
                 "1.9.8"
             ]
+        },
+        "dependencies": {
+            "1.9.8": {
+                "net.mamoe:mirai-console": ">=2.0"
+            }
         }
     }"#;
 
@@ -271,6 +462,34 @@ mod tests {
         let expect: Versions = vec!["1.9.6", "1.9.7", "1.9.8"].into_iter().map(ToString::to_string).collect();
 
         assert_eq!(&expect, stable_channel);
+
+        let deps = package.dependencies_of("1.9.8");
+
+        assert_eq!(">=2.0", deps[&mirai_console_id()].to_string());
+        assert!(package.dependencies_of("1.9.7").is_empty());
+        assert_eq!(None, package.suffix_of("1.9.8"));
+    }
+
+    #[test]
+    fn parse_artifact() {
+        let json = r#"{
+            "channels": { "stable": ["2.3.2", "2.4.0", "2.5.0"] },
+            "artifact": { "classifier": "all" },
+            "artifacts": {
+                "2.3.2": ".jar",
+                "2.5.0": { "extension": "mirai.jar" }
+            }
+        }"#;
+
+        let package = PackageVersion::from_value(serde_json::from_str(json).unwrap()).unwrap();
+
+        assert_eq!(Some("-all.jar"), package.suffix_of("2.4.0"));
+        assert_eq!(Some(".jar"), package.suffix_of("2.3.2"));
+        assert_eq!(Some(".mirai.jar"), package.suffix_of("2.5.0"));
+
+        let invalid = r#"{ "channels": {}, "artifact": "jar" }"#;
+
+        assert!(PackageVersion::from_value(serde_json::from_str(invalid).unwrap()).is_err());
     }
 
     /**
@@ -285,6 +504,17 @@ mod tests {
         println!("{:?}", package);
     }
 
+    #[test]
+    fn match_pid() {
+        let pid = mirai_console_id();
+
+        assert!(pid.matches("net.mamoe:*"));
+        assert!(pid.matches("net.*"));
+        assert!(pid.matches("*:mirai-*"));
+        assert!(!pid.matches("com.ourteam:*"));
+        assert!(!pid.matches("net.mamoe:chat-command"));
+    }
+
     #[test]
     fn deser_pid() {
         let pid = "\"net.mamoe:mirai-console\"";
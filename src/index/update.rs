@@ -1,12 +1,18 @@
 use std::{fs::File, io::{BufReader, Write}, marker::PhantomData, path::{Path, PathBuf}, process::{ExitStatus, Stdio}};
 use std::process::Command;
+use std::sync::Arc;
 
-use git2::{Repository, ResetType};
+use git2::{build::RepoBuilder, FetchOptions, ProxyOptions, RemoteCallbacks, Repository, ResetType};
 use anyhow::Result;
 
 use crate::logger::{Logger, StdioLogger};
 use crate::error::{AsResult, index_err};
 use crate::index::package::Packages;
+use crate::index::verify::{CommitVerifier, IndexPolicy};
+use crate::net::{Rate, RateLimiter};
+use crate::progress::{self, DownloadObserver, Phase, Progress};
+use crate::proxy::ProxySettings;
+use crate::signature::Keyring;
 
 #[derive(Debug, Clone)]
 pub struct MirrorRepo {
@@ -35,6 +41,11 @@ impl MirrorRepo {
 pub struct Updater<Log: Logger> {
     repo: MirrorRepo,
     dir: PathBuf,
+    keyring_path: PathBuf,
+    policy: IndexPolicy,
+    observer: Arc<dyn DownloadObserver>,
+    limit_rate: Option<Rate>,
+    proxies: ProxySettings,
     _phantom: PhantomData<Log>
 }
 
@@ -43,10 +54,92 @@ impl <Log: Logger> Updater<Log> {
         Updater {
             repo,
             dir,
+            keyring_path: PathBuf::new(),
+            policy: IndexPolicy::default(),
+            observer: progress::observer(),
+            limit_rate: None,
+            proxies: ProxySettings::default(),
             _phantom: PhantomData::default()
         }
     }
 
+    /**
+     * Require index commits to be signed by a key of `policy`, OpenPGP keys are looked up in the keyring at `keyring_path`.
+     */
+    pub fn verify_commits(mut self, keyring_path: PathBuf, policy: IndexPolicy) -> Self {
+        self.keyring_path = keyring_path;
+        self.policy = policy;
+        self
+    }
+
+    /**
+     * Report the progress of fetching the index to `observer`.
+     */
+    pub fn observer(mut self, observer: Arc<dyn DownloadObserver>) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    /**
+     * Throttle index fetches to `rate`.
+     */
+    pub fn limit_rate(mut self, rate: Option<Rate>) -> Self {
+        self.limit_rate = rate;
+        self
+    }
+
+    /**
+     * Fetch the index through the proxy `proxies` choose for the mirror, only HTTP proxies are supported by libgit2.
+     */
+    pub fn proxy(mut self, proxies: ProxySettings) -> Self {
+        self.proxies = proxies;
+        self
+    }
+
+    /**
+     * Fetch options reporting the bytes received to `progress`. libgit2 has no rate limit, so the
+     * progress callback holds up the transfer instead, which only affects fetches over the network.
+     */
+    fn fetch_options<'a>(&self, progress: &'a Progress) -> FetchOptions<'a> {
+        let rate = RateLimiter::new(self.limit_rate);
+        let mut received = 0;
+
+        let mut callbacks = RemoteCallbacks::new();
+        callbacks.transfer_progress(move |stats| {
+            progress.update(stats.received_bytes() as u64);
+
+            std::thread::sleep(rate.reserve(stats.received_bytes().saturating_sub(received)));
+            received = stats.received_bytes();
+
+            true
+        });
+
+        let mut options = FetchOptions::new();
+        options.remote_callbacks(callbacks);
+
+        match self.proxies.for_git(&self.repo.url) {
+            Ok(Some(url)) => {
+                let mut proxy = ProxyOptions::new();
+                proxy.url(&url);
+                options.proxy_options(proxy);
+            }
+
+            Ok(None) => {}
+            Err(err) => self.observer.warn(&format!("Warning: {}, connecting directly.", err))
+        }
+        options
+    }
+
+    fn verifier(&self) -> Result<Option<CommitVerifier>> {
+        if !self.policy.require_signature {
+            return Ok(None);
+        }
+
+        let keyring = Keyring::load(&self.keyring_path)?;
+
+        Ok(Some(CommitVerifier::new(&self.policy.trusted_keys, &keyring)?))
+    }
+
     pub fn repo(&self) -> &MirrorRepo {
         &self.repo
     }
@@ -56,21 +149,58 @@ impl <Log: Logger> Updater<Log> {
     }
 
     pub fn update(&self) -> Result<()> {
+        let verifier = self.verifier()?;
+
+        self.observer.phase(&Phase::Update { url: &self.repo.url });
+
         if self.dir.exists() {
             let repo = Repository::open(&self.dir)?;
             let mut remote = repo.find_remote("origin")?;
 
-            remote.fetch(&["master"], None, None)?;
+            let progress = Progress::start(&self.observer, "index", 0, None);
+            let fetched = remote.fetch(&["master"], Some(&mut self.fetch_options(&progress)), None);
+            progress.finish(fetched)?;
             
             let remote_master = repo.find_reference(&format!("refs/remotes/origin/{}", self.repo.branch))?;
             let commit_oid = remote_master.target().ok_or(index_err("failed to get reference target"))?;
             let commit_object = repo.find_object(commit_oid, None)?;
 
+            if let Some(verifier) = &verifier {
+                let signer = match verifier.verify(&repo, commit_oid) {
+                    Ok(signer) => signer,
+                    Err(err) => {
+                        self.observer.warn("Rejected index update, the index is left unchanged.");
+
+                        return Err(err);
+                    }
+                };
+
+                writeln!(Log::info(), "Index commit {} is signed by {}.", commit_oid, signer)?;
+            }
+
             repo.reset(&commit_object, ResetType::Hard, None)?;
         } else {
             writeln!(Log::info(), "Index folder not found.")?;
 
-            Repository::clone(&self.repo.url, &self.dir)?;
+            let progress = Progress::start(&self.observer, "index", 0, None);
+            let cloned = RepoBuilder::new()
+                .fetch_options(self.fetch_options(&progress))
+                .clone(&self.repo.url, &self.dir);
+            let repo = progress.finish(cloned)?;
+
+            if let Some(verifier) = &verifier {
+                let head = repo.head()?.target().ok_or(index_err("failed to get reference target"))?;
+
+                match verifier.verify(&repo, head) {
+                    Ok(signer) => writeln!(Log::info(), "Index commit {} is signed by {}.", head, signer)?,
+                    Err(err) => {
+                        std::fs::remove_dir_all(&self.dir)?;
+                        self.observer.warn("Rejected index, the cloned index is removed.");
+
+                        return Err(err);
+                    }
+                }
+            }
         }
 
         Ok(())
@@ -0,0 +1,156 @@
+use std::io::Cursor;
+
+use anyhow::Result;
+use git2::{Oid, Repository};
+use pgp::{Deserializable, SignedPublicKey, StandaloneSignature};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{index_err, parse_err};
+use crate::signature::{parse_key, Keyring};
+
+/// `[index]` section of the config file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IndexPolicy {
+    /// Reject index updates whose commit is not signed by one of `trusted_keys`.
+    pub require_signature: bool,
+
+    /// OpenPGP fingerprints (or long key IDs) of keys in the keyring,
+    /// or SSH public keys such as `ssh-ed25519 AAAA... ci@mirai-repo`.
+    pub trusted_keys: Vec<String>
+}
+
+enum TrustedKey {
+    OpenPgp(String, SignedPublicKey),
+    Ssh(ssh_key::PublicKey)
+}
+
+/// Checks that index commits are signed by a trusted key.
+pub struct CommitVerifier {
+    keys: Vec<TrustedKey>
+}
+
+impl CommitVerifier {
+    pub fn new(trusted_keys: &[String], keyring: &Keyring) -> Result<CommitVerifier> {
+        let keys = trusted_keys
+            .iter()
+            .map(|trusted| {
+                if trusted.starts_with("ssh-") || trusted.starts_with("ecdsa-") {
+                    let key = ssh_key::PublicKey::from_openssh(trusted)
+                        .map_err(|err| parse_err(format!("invalid SSH public key '{}': {}", trusted, err)))?;
+
+                    Ok(TrustedKey::Ssh(key))
+                } else {
+                    let (fingerprint, entry) = keyring.find(trusted)?;
+
+                    Ok(TrustedKey::OpenPgp(format!("{} ({})", entry.uid, fingerprint), parse_key(&entry.armored)?))
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if keys.is_empty() {
+            return Err(index_err("signed index is required, but no trusted key is configured").into());
+        }
+
+        Ok(CommitVerifier { keys })
+    }
+
+    /**
+     * Verify the signature of commit `oid`, returns who signed it.
+     */
+    pub fn verify(&self, repo: &Repository, oid: Oid) -> Result<String> {
+        let (signature, data) = repo.extract_signature(&oid, None)
+            .map_err(|_| index_err(format!("commit {} is not signed", oid)))?;
+
+        let signature = signature.as_str()
+            .ok_or_else(|| index_err(format!("signature of commit {} is not valid UTF-8", oid)))?;
+
+        let signer = if signature.trim_start().starts_with("-----BEGIN SSH SIGNATURE-----") {
+            let sig: ssh_key::SshSig = signature.parse()
+                .map_err(|err| index_err(format!("malformed signature of commit {}: {}", oid, err)))?;
+
+            self.keys.iter().find_map(|key| match key {
+                TrustedKey::Ssh(key) if key.verify("git", &data, &sig).is_ok() => Some(key.fingerprint(Default::default()).to_string()),
+                _ => None
+            })
+        } else {
+            let (sig, _) = StandaloneSignature::from_armor_single(Cursor::new(signature.as_bytes()))
+                .map_err(|err| index_err(format!("malformed signature of commit {}: {}", oid, err)))?;
+
+            self.keys.iter().find_map(|key| match key {
+                TrustedKey::OpenPgp(name, key) => {
+                    let good = sig.verify(key, &data).is_ok()
+                        || key.public_subkeys.iter().any(|sub| sig.verify(sub, &data).is_ok());
+
+                    if good { Some(name.clone()) } else { None }
+                }
+                _ => None
+            })
+        };
+
+        signer.ok_or_else(|| index_err(format!("commit {} is not signed by a trusted key", oid)).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use git2::Signature;
+
+    use super::*;
+
+    // generated with `ssh-keygen -t ed25519 -N "" -C ci@mirai-repo`, only used to sign test commits
+    const PRIVATE_KEY: &str = include_str!("../../tests/data/index_ed25519");
+    const PUBLIC_KEY: &str = include_str!("../../tests/data/index_ed25519.pub");
+
+    fn commit(dir: &str, signed: bool) -> (Repository, Oid) {
+        let mut path = std::env::temp_dir();
+        path.push(dir);
+
+        let _ = std::fs::remove_dir_all(&path);
+
+        let repo = Repository::init(&path).unwrap();
+        let tree = repo.find_tree(repo.index().unwrap().write_tree().unwrap()).unwrap();
+        let author = Signature::now("mirai-repo", "ci@mirai-repo").unwrap();
+        let buffer = repo.commit_create_buffer(&author, &author, "update index", &tree, &[]).unwrap();
+        let content = buffer.as_str().unwrap().to_string();
+
+        let oid = if signed {
+            let key = ssh_key::PrivateKey::from_openssh(PRIVATE_KEY).unwrap();
+            let sig = key.sign("git", ssh_key::HashAlg::Sha512, content.as_bytes()).unwrap();
+
+            repo.commit_signed(&content, &sig.to_pem(ssh_key::LineEnding::LF).unwrap(), None).unwrap()
+        } else {
+            repo.commit(None, &author, &author, "update index", &tree, &[]).unwrap()
+        };
+
+        drop(tree);
+
+        (repo, oid)
+    }
+
+    fn verifier() -> CommitVerifier {
+        CommitVerifier::new(&[PUBLIC_KEY.trim().to_string()], &Keyring::default()).unwrap()
+    }
+
+    #[test]
+    fn ssh_signed_commit() {
+        let (repo, oid) = commit("mpt-get-signed-index", true);
+
+        assert!(verifier().verify(&repo, oid).unwrap().starts_with("SHA256:"));
+    }
+
+    #[test]
+    fn unsigned_commit() {
+        let (repo, oid) = commit("mpt-get-unsigned-index", false);
+
+        assert!(verifier().verify(&repo, oid).is_err());
+    }
+
+    #[test]
+    fn untrusted_key() {
+        let keyring = Keyring::default();
+
+        assert!(CommitVerifier::new(&[], &keyring).is_err());
+        assert!(CommitVerifier::new(&[String::from("DEADBEEFDEADBEEF")], &keyring).is_err());
+    }
+}
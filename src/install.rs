@@ -0,0 +1,758 @@
+use std::{collections::{BTreeMap, HashMap}, fs, io::Write, marker::PhantomData, path::{Path, PathBuf}, sync::Arc};
+
+use anyhow::Result;
+use futures::future::join_all;
+use tokio::runtime::Runtime;
+
+use crate::auth::CredentialStore;
+use crate::cache::{Cache, Freed};
+use crate::checksum::Checksums;
+use crate::error::install_err;
+use crate::get::{route, Downloader, Estimate, SourceRepo};
+use crate::index::{PackageID, PackageVersion, Packages};
+use crate::logger::Logger;
+use crate::metadata::Metadata;
+use crate::net::{DownloadPolicy, HostLimiter, NetworkConfig, RateLimiter, RetryPolicy, TlsConfig};
+use crate::pom::{Library, LibraryPolicy, PomResolver};
+use crate::progress::{self, DownloadObserver, Phase};
+use crate::space::SpaceNeeds;
+use crate::proxy::ProxySettings;
+use crate::resolve::{Resolution, Resolved, Resolver};
+use crate::signature::{Keyring, SignaturePolicy};
+use crate::state::{InstalledLibrary, InstalledPackage, InstalledState};
+
+
+/// An artifact to download into `dir`.
+#[derive(Debug, PartialEq, Eq)]
+struct Download {
+    id: PackageID,
+    version: String,
+    suffix: Option<String>,
+    expected: Checksums,
+    dir: PathBuf
+}
+
+impl Download {
+    /**
+     * Index of `download` in `downloads`, which is added unless another package already needs it.
+     */
+    fn add(downloads: &mut Vec<Download>, download: Download) -> usize {
+        match downloads.iter().position(|other| *other == download) {
+            Some(index) => index,
+            None => {
+                downloads.push(download);
+                downloads.len() - 1
+            }
+        }
+    }
+}
+
+/// A package to install, with the indices of its downloads.
+struct Plan {
+    pid: PackageID,
+    resolved: Resolved,
+    artifact: usize,
+    reused: Vec<InstalledLibrary>,
+    libraries: Vec<usize>
+}
+
+/**
+ * Packages not to install, with how they are shown to the user: those whose downloads `failed`,
+ * then those depending on a skipped package, until nothing changes.
+ */
+fn skipped(plans: &[Plan], failed: impl Fn(&Plan) -> bool) -> BTreeMap<PackageID, String> {
+    let mut skipped: BTreeMap<PackageID, String> = plans.iter()
+        .filter(|plan| failed(plan))
+        .map(|plan| (plan.pid.clone(), plan.pid.to_string()))
+        .collect();
+
+    loop {
+        let dependents: Vec<(PackageID, String)> = plans.iter()
+            .filter(|plan| !skipped.contains_key(&plan.pid))
+            .filter_map(|plan| {
+                let dep = plan.resolved.dependencies.iter().find(|dep| skipped.contains_key(dep))?;
+                Some((plan.pid.clone(), format!("{} (needs {})", plan.pid, dep)))
+            })
+            .collect();
+
+        if dependents.is_empty() {
+            return skipped;
+        }
+
+        skipped.extend(dependents);
+    }
+}
+
+#[derive(Debug)]
+pub struct Installer<Log: Logger> {
+    repos: Vec<SourceRepo>,
+    index_dir: PathBuf,
+    pkg_dir: PathBuf,
+    lib_dir: PathBuf,
+    state_path: PathBuf,
+    insecure: bool,
+    keyring_path: PathBuf,
+    policy: SignaturePolicy,
+    tls: TlsConfig,
+    proxies: ProxySettings,
+    network: NetworkConfig,
+    retry: RetryPolicy,
+    credentials: CredentialStore,
+    libraries: LibraryPolicy,
+    cache: Option<Cache>,
+    downloads: DownloadPolicy,
+    observer: Arc<dyn DownloadObserver>,
+    _phantom: PhantomData<Log>
+}
+
+impl <Log: Logger> Installer<Log> {
+    pub fn new(repos: Vec<SourceRepo>, index_dir: PathBuf, pkg_dir: PathBuf, state_path: PathBuf) -> Installer<Log> {
+        Installer {
+            repos,
+            index_dir,
+            pkg_dir,
+            lib_dir: PathBuf::new(),
+            state_path,
+            insecure: false,
+            keyring_path: PathBuf::new(),
+            policy: SignaturePolicy::default(),
+            tls: TlsConfig::default(),
+            proxies: ProxySettings::default(),
+            network: NetworkConfig::default(),
+            retry: RetryPolicy::default(),
+            credentials: CredentialStore::default(),
+            libraries: LibraryPolicy { resolve: false, provided: Vec::new() },
+            cache: None,
+            downloads: DownloadPolicy::default(),
+            observer: progress::observer(),
+            _phantom: PhantomData
+        }
+    }
+
+    /**
+     * Verify signatures with the keyring at `keyring_path`, following `policy`.
+     */
+    pub fn signatures(mut self, keyring_path: PathBuf, policy: SignaturePolicy) -> Self {
+        self.keyring_path = keyring_path;
+        self.policy = policy;
+        self
+    }
+
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    pub fn proxy(mut self, proxies: ProxySettings) -> Self {
+        self.proxies = proxies;
+        self
+    }
+
+    pub fn network(mut self, network: NetworkConfig) -> Self {
+        self.network = network;
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn credentials(mut self, store: CredentialStore) -> Self {
+        self.credentials = store;
+        self
+    }
+
+    /**
+     * Download runtime libraries of packages into `lib_dir`, following `policy`.
+     */
+    pub fn libraries(mut self, lib_dir: PathBuf, policy: LibraryPolicy) -> Self {
+        self.lib_dir = lib_dir;
+        self.libraries = policy;
+        self
+    }
+
+    pub fn downloads(mut self, policy: DownloadPolicy) -> Self {
+        self.downloads = policy;
+        self
+    }
+
+    /**
+     * Report the progress of transactions to `observer`.
+     */
+    pub fn observer(mut self, observer: Arc<dyn DownloadObserver>) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    pub fn cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /**
+     * Install packages without verifying their checksums and signatures.
+     */
+    pub fn insecure(mut self, insecure: bool) -> Self {
+        self.insecure = insecure;
+        self
+    }
+
+    pub fn state(&self) -> Result<InstalledState> {
+        InstalledState::load(&self.state_path)
+    }
+
+    pub fn save_state(&self, state: &InstalledState) -> Result<()> {
+        state.save(&self.state_path)
+    }
+
+    fn index(&self) -> Result<Packages> {
+        let mut index = self.index_dir.clone();
+        index.push("packages.json");
+
+        Packages::from_file(index)
+    }
+
+    /**
+     * Check the requested version against the index and the pin of `pid`, so the user gets
+     * a direct answer instead of a resolver conflict.
+     */
+    fn check_version(&self, state: &InstalledState, pid: &PackageID, version: &str, force: bool) -> Result<()> {
+        let vers = PackageVersion::from_pid(pid, &self.index_dir)?;
+
+        if vers.channel_of(version).is_none() {
+            return Err(install_err(format!("version {} of {} not found. Try to update index.", version, pid)).into());
+        }
+
+        self.check_policy(state, pid, version, force)
+    }
+
+    fn check_policy(&self, state: &InstalledState, pid: &PackageID, version: &str, force: bool) -> Result<()> {
+        if force {
+            return Ok(());
+        }
+
+        if let Some(pin) = state.pin(pid) {
+            if !pin.matches_str(version) {
+                return Err(install_err(format!("{} is pinned to {}, refusing to install {} (use --force to override)", pid, pin, version)).into());
+            }
+        }
+
+        if let (true, Some(installed)) = (state.is_held(pid), state.packages.get(pid)) {
+            if installed.version != version {
+                return Err(install_err(format!("{} is held at {}, refusing to install {} (use --force to override)", pid, installed.version, version)).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn downloader(&self, pid: &PackageID) -> Result<Downloader> {
+        self.downloader_into(pid, &self.pkg_dir)
+    }
+
+    fn downloader_into(&self, pid: &PackageID, dir: &Path) -> Result<Downloader> {
+        let repos = route(&self.repos, pid);
+
+        if repos.is_empty() {
+            return Err(install_err(format!("no repository serves {}, check include and exclude of [[repositories]] in the config", pid)).into());
+        }
+
+        let mut downloader = Downloader::new(repos, dir.to_path_buf())
+            .insecure(self.insecure)
+            .tls(self.tls.clone())
+            .proxy(self.proxies.clone())
+            .network(self.network.clone())
+            .retry(self.retry)
+            .credentials(self.credentials.clone())
+            .observer(self.observer.clone());
+
+        if let Some(cache) = &self.cache {
+            downloader = downloader.cache(cache.clone());
+        }
+
+        if self.policy.enabled() {
+            downloader = downloader.signatures(Keyring::load(&self.keyring_path)?, self.policy);
+        }
+
+        Ok(downloader)
+    }
+
+    /**
+     * `maven-metadata.xml` of `pid` from the first repository serving it that publishes one.
+     */
+    pub fn remote_metadata(&self, pid: &PackageID) -> Result<Option<(SourceRepo, Metadata)>> {
+        let downloader = self.downloader(pid)?;
+
+        Runtime::new()?.block_on(downloader.metadata(pid))
+    }
+
+    /**
+     * Download every artifact of `fetches` and their libraries at once, then record the packages
+     * whose artifacts all arrived. Failed downloads are reported together at the end.
+     */
+    fn fetch(&self, state: &mut InstalledState, fetches: Vec<(PackageID, Resolved)>) -> Result<()> {
+        let runtime = Runtime::new()?;
+        let mut downloads: Vec<Download> = Vec::new();
+        let mut plans = Vec::new();
+
+        for (pid, resolved) in fetches {
+            let vers = PackageVersion::from_pid(&pid, &self.index_dir).ok();
+
+            writeln!(Log::info(), "Downloading {} {}...", pid, resolved.version)?;
+
+            if self.insecure {
+                self.observer.warn(&format!("Warning: checksum and signature of {} {} will not be verified.", pid, resolved.version));
+            }
+
+            let (reused, libraries) = self.plan_libraries(&runtime, state, &pid, &resolved.version)?;
+
+            let libraries = libraries.into_iter()
+                .map(|library| Download::add(&mut downloads, Download {
+                    dir: self.library_dir(&library.id),
+                    id: library.id,
+                    version: library.version,
+                    suffix: Some(library.suffix),
+                    expected: Checksums::new()
+                }))
+                .collect();
+
+            let artifact = Download::add(&mut downloads, Download {
+                id: pid.clone(),
+                version: resolved.version.clone(),
+                suffix: vers.as_ref().and_then(|vers| vers.suffix_of(&resolved.version)).map(String::from),
+                expected: vers.as_ref().map(|vers| vers.checksums_of(&resolved.version)).unwrap_or_default(),
+                dir: self.pkg_dir.clone()
+            });
+
+            plans.push(Plan { pid, resolved, artifact, reused, libraries });
+        }
+
+        let limiter = HostLimiter::new(self.downloads.max_connections_per_host);
+        let rate = RateLimiter::new(self.downloads.limit_rate);
+        let downloaders = downloads.iter()
+            .map(|download| Ok(self.downloader_into(&download.id, &download.dir)?.concurrent(limiter.clone(), rate.clone())))
+            .collect::<Result<Vec<_>>>()?;
+
+        let estimates = runtime.block_on(join_all(downloads.iter().zip(downloaders.iter()).map(|(download, downloader)| {
+            downloader.estimate(&download.id, &download.version, download.suffix.as_deref())
+        })));
+
+        self.check_space(&downloads, &estimates)?;
+        self.observer.phase(&Phase::Download { artifacts: downloads.len() });
+
+        let results = runtime.block_on(join_all(downloads.iter().zip(downloaders.iter()).map(|(download, downloader)| {
+            downloader.download(&download.id, &download.version, download.suffix.as_deref(), &download.expected)
+        })));
+
+        let skipped = skipped(&plans, |plan| plan.libraries.iter().chain(Some(&plan.artifact)).any(|index| results[*index].is_err()));
+        let mut unused = Vec::new();
+
+        for plan in plans {
+            let files: Option<Vec<&PathBuf>> = plan.libraries.iter()
+                .map(|index| results[*index].as_ref().ok())
+                .collect();
+
+            let (file, files) = match (&results[plan.artifact], files) {
+                (Ok(file), Some(files)) if !skipped.contains_key(&plan.pid) => (file.clone(), files),
+                _ => {
+                    unused.extend(plan.libraries.iter().chain(Some(&plan.artifact)).filter_map(|index| results[*index].as_ref().ok()));
+                    continue;
+                }
+            };
+
+            let libraries = plan.reused.into_iter()
+                .chain(plan.libraries.iter().zip(files).map(|(index, file)| InstalledLibrary {
+                    id: downloads[*index].id.clone(),
+                    version: downloads[*index].version.clone(),
+                    file: file.clone()
+                }))
+                .collect();
+
+            self.record(state, plan.pid, plan.resolved, file, libraries)?;
+        }
+
+        // files downloaded for packages that are not installed, unless an installed package uses them
+        for file in unused {
+            let used = state.packages.values().any(|pkg| pkg.file == *file || pkg.libraries.iter().any(|library| library.file == *file));
+
+            if !used && file.exists() {
+                fs::remove_file(file)?;
+            }
+        }
+
+        let failures: Vec<String> = downloads.iter()
+            .zip(results.iter())
+            .filter_map(|(download, result)| Some(format!("  {} {}: {}", download.id, download.version, result.as_ref().err()?)))
+            .collect();
+
+        if !failures.is_empty() {
+            return Err(install_err(format!("failed to download {} of {} artifacts, {} not installed:\n{}",
+                failures.len(), downloads.len(), skipped.into_values().collect::<Vec<_>>().join(", "), failures.join("\n"))).into());
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Make sure the filesystems written to have room for `downloads`: their directories, which also hold the
+     * partial downloads, and the cache, which keeps a copy of every new download.
+     */
+    fn check_space(&self, downloads: &[Download], estimates: &[Estimate]) -> Result<()> {
+        let mut needs = SpaceNeeds::default();
+
+        for (download, estimate) in downloads.iter().zip(estimates) {
+            match *estimate {
+                Estimate::Cached(size) => needs.add(&download.dir, size),
+                Estimate::Download(size) => {
+                    needs.add(&download.dir, size);
+
+                    if let Some(cache) = &self.cache {
+                        needs.add(cache.dir(), size);
+                    }
+                }
+
+                Estimate::Unknown => needs.unknown += 1
+            }
+        }
+
+        if needs.unknown > 0 {
+            self.observer.warn(&format!("Warning: the size of {} of {} artifacts is unknown, the disk space check only counts the others.", needs.unknown, downloads.len()));
+        }
+
+        needs.check()
+    }
+
+    /**
+     * Record `pid` as installed from `file`, removing the files of the version it replaces.
+     */
+    fn record(&self, state: &mut InstalledState, pid: PackageID, resolved: Resolved, file: PathBuf, libraries: Vec<InstalledLibrary>) -> Result<()> {
+        self.observer.phase(&Phase::Install { package: pid.to_string(), version: &resolved.version });
+
+        let old = state.packages.insert(pid, InstalledPackage {
+            version: resolved.version,
+            channel: resolved.channel,
+            file: file.clone(),
+            auto: !resolved.explicit,
+            libraries
+        });
+
+        if let Some(old) = old {
+            if old.file != file && old.file.exists() {
+                fs::remove_file(&old.file)?;
+            }
+
+            for library in old.libraries {
+                let used = state.packages.values().any(|pkg| pkg.libraries.iter().any(|other| other.file == library.file));
+
+                if !used && library.file.exists() {
+                    writeln!(Log::info(), "Removing library {} {}, no longer needed.", library.id, library.version)?;
+                    fs::remove_file(&library.file)?;
+
+                    // the group directory, once its last library is gone
+                    if let Some(dir) = library.file.parent().filter(|dir| *dir != self.lib_dir) {
+                        let _ = fs::remove_dir(dir);
+                    }
+                }
+            }
+        }
+
+        self.save_state(state)
+    }
+
+    /**
+     * Directory of the library `id`, a subdirectory of `lib_dir` named after its group, so that
+     * artifacts with the same name from different groups do not overwrite each other.
+     */
+    fn library_dir(&self, id: &PackageID) -> PathBuf {
+        self.lib_dir.join(&id.domain)
+    }
+
+    /**
+     * Runtime libraries declared in the POM of `pid`, except those mirai-console provides and
+     * packages of the index, which are installed as packages instead. Returns the libraries other
+     * packages already installed, and those to download.
+     */
+    fn plan_libraries(&self, runtime: &Runtime, state: &InstalledState, pid: &PackageID, version: &str) -> Result<(Vec<InstalledLibrary>, Vec<Library>)> {
+        if !self.libraries.resolve {
+            return Ok((Vec::new(), Vec::new()));
+        }
+
+        let mut provided = self.libraries.provided.clone();
+
+        if let Ok(index) = self.index() {
+            provided.extend(index.map.keys().map(ToString::to_string));
+        }
+
+        writeln!(Log::info(), "Resolving libraries of {} {}...", pid, version)?;
+
+        let mut resolver = PomResolver::new(|id: &PackageID, version: &str| runtime.block_on(self.downloader_into(id, &self.lib_dir)?.pom(id, version)));
+        let libraries = resolver.libraries(pid, version, &provided)?;
+
+        for warning in &resolver.warnings {
+            self.observer.warn(&format!("Warning: {}", warning));
+        }
+
+        let libraries = match libraries {
+            Some(libraries) => libraries,
+            None => {
+                writeln!(Log::info(), "No POM of {} {} is published, its libraries are not resolved.", pid, version)?;
+                return Ok((Vec::new(), Vec::new()));
+            }
+        };
+
+        let installed: Vec<&InstalledLibrary> = state.packages
+            .iter()
+            .filter(|(other, _)| *other != pid)
+            .flat_map(|(_, pkg)| pkg.libraries.iter())
+            .collect();
+
+        let mut reused = Vec::new();
+        let mut needed = Vec::new();
+
+        for library in libraries {
+            let file = self.library_dir(&library.id).join(format!("{}-{}{}", library.id.name, library.version, library.suffix));
+
+            if let Some(shared) = installed.iter().find(|other| other.id == library.id && other.file == file && file.exists()) {
+                reused.push((*shared).clone());
+                continue;
+            }
+
+            if let Some(other) = installed.iter().find(|other| other.id == library.id) {
+                self.observer.warn(&format!("Warning: {} needs {} {} while {} is installed, both end up in {:?}.", pid, library.id, library.version, other.version, self.library_dir(&library.id)));
+            }
+
+            writeln!(Log::info(), "Downloading library {} {}...", library.id, library.version)?;
+
+            needed.push(library);
+        }
+
+        Ok((reused, needed))
+    }
+
+    /**
+     * Download every package whose resolved version differs from the installed one.
+     */
+    fn apply(&self, state: &mut InstalledState, resolution: Resolution) -> Result<()> {
+        let mut fetches = Vec::new();
+
+        for (pid, resolved) in resolution {
+            match state.packages.get_mut(&pid) {
+                Some(installed) if installed.version == resolved.version => {
+                    if installed.auto && resolved.explicit {
+                        installed.auto = false;
+                        self.save_state(state)?;
+                    }
+                }
+
+                Some(installed) => {
+                    writeln!(Log::info(), "{}: {} -> {}", pid, installed.version, resolved.version)?;
+
+                    fetches.push((pid, resolved));
+                }
+
+                None => {
+                    if !resolved.explicit {
+                        writeln!(Log::info(), "{} {} is needed as a dependency.", pid, resolved.version)?;
+                    }
+
+                    fetches.push((pid, resolved));
+                }
+            }
+        }
+
+        self.fetch(state, fetches)
+    }
+
+    pub fn install(&self, pid: &PackageID, version: Option<&str>, force: bool) -> Result<()> {
+        let index = self.index()?;
+
+        if !index.map.contains_key(pid) {
+            return Err(install_err(format!("package {} not found. Try to update index.", pid)).into());
+        }
+
+        let mut state = self.state()?;
+
+        let latest = match version {
+            Some("latest") => Some(self.latest(pid)?),
+            _ => None
+        };
+
+        let version = latest.as_deref().or(version);
+
+        if let (Some(latest), Ok(vers)) = (&latest, PackageVersion::from_pid(pid, &self.index_dir)) {
+            if vers.channel_of(latest).is_none() {
+                return self.install_unindexed(&mut state, pid, latest, force);
+            }
+        }
+
+        if let Some(version) = version {
+            self.check_version(&state, pid, version, force)?;
+        }
+
+        let req = version.map(str::parse).transpose()?;
+
+        self.observer.phase(&Phase::Resolve);
+
+        let mut resolver = Resolver::from_index(&index, &self.index_dir, &state)?;
+
+        // --force overrides the pin and hold of the requested package only
+        if force {
+            resolver = resolver.force(pid);
+        }
+
+        let resolution = resolver.resolve(&[(pid.clone(), req)], false)?;
+
+        if let (Some(installed), Some(resolved)) = (state.packages.get(pid), resolution.get(pid)) {
+            if installed.version == resolved.version {
+                writeln!(Log::info(), "{} {} is already installed.", pid, installed.version)?;
+            }
+        }
+
+        self.apply(&mut state, resolution)
+    }
+
+    /**
+     * The newest version of `pid` published in its repositories.
+     */
+    fn latest(&self, pid: &PackageID) -> Result<String> {
+        let (repo, metadata) = self.remote_metadata(pid)?
+            .ok_or_else(|| install_err(format!("no repository publishes maven-metadata.xml of {}", pid)))?;
+
+        let newest = metadata.newest()
+            .ok_or_else(|| install_err(format!("maven-metadata.xml of {} in {} lists no version", pid, repo)))?;
+
+        writeln!(Log::info(), "The latest version of {} in {} is {}.", pid, repo, newest)?;
+
+        Ok(newest.clone())
+    }
+
+    /**
+     * Install a version the index does not know yet, so neither its dependencies nor its checksums are available.
+     */
+    fn install_unindexed(&self, state: &mut InstalledState, pid: &PackageID, version: &str, force: bool) -> Result<()> {
+        self.check_policy(state, pid, version, force)?;
+
+        if state.packages.get(pid).map(|installed| installed.version == version).unwrap_or(false) {
+            writeln!(Log::info(), "{} {} is already installed.", pid, version)?;
+            return Ok(());
+        }
+
+        self.observer.warn(&format!("Warning: {} {} is not in the index yet, its dependencies will not be installed. Try to update index.", pid, version));
+
+        self.fetch(state, vec![(pid.clone(), Resolved {
+            channel: String::from("remote"),
+            version: version.to_string(),
+            explicit: true,
+            dependencies: Vec::new()
+        })])
+    }
+
+    /**
+     * Remove every cached artifact.
+     */
+    pub fn clean(&self) -> Result<Freed> {
+        match &self.cache {
+            Some(cache) => cache.clear(),
+            None => Ok(Freed::default())
+        }
+    }
+
+    /**
+     * Remove cached artifacts that neither an installed package nor a version in the index refers to,
+     * and those beyond the limits of the cache.
+     */
+    pub fn autoclean(&self) -> Result<Freed> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return Ok(Freed::default())
+        };
+
+        let state = self.state()?;
+        let indexed: HashMap<PackageID, PackageVersion> = match self.index() {
+            Ok(index) => index.map.keys()
+                .filter_map(|pid| Some((pid.clone(), PackageVersion::from_pid(pid, &self.index_dir).ok()?)))
+                .collect(),
+            Err(_) => HashMap::new()
+        };
+
+        cache.prune(|pid, version| {
+            let installed = state.packages.iter().any(|(installed, pkg)| {
+                (installed == pid && pkg.version == version)
+                    || pkg.libraries.iter().any(|library| &library.id == pid && library.version == version)
+            });
+
+            installed || indexed.get(pid).map(|vers| vers.channel_of(version).is_some()).unwrap_or(false)
+        })
+    }
+
+    /**
+     * Upgrade every installed package to the newest version allowed by holds, pins and dependencies.
+     */
+    pub fn upgrade(&self) -> Result<()> {
+        let index = self.index()?;
+        let mut state = self.state()?;
+
+        for (pid, pkg) in state.packages.iter() {
+            if state.is_held(pid) {
+                writeln!(Log::info(), "Skipping {}: held at {}. Use mpt-get unhold {} to allow upgrading.", pid, pkg.version, pid)?;
+            }
+        }
+
+        self.observer.phase(&Phase::Resolve);
+
+        let resolver = Resolver::from_index(&index, &self.index_dir, &state)?;
+        let resolution = resolver.resolve(&[], true)?;
+
+        for (pid, pkg) in state.packages.iter() {
+            let unchanged = resolution.get(pid).map(|resolved| resolved.version == pkg.version).unwrap_or(true);
+
+            if unchanged && !state.is_held(pid) {
+                match state.pin(pid) {
+                    Some(pin) => writeln!(Log::info(), "{} {} is the newest version allowed by pin {}.", pid, pkg.version, pin)?,
+                    None => writeln!(Log::info(), "{} {} is up to date.", pid, pkg.version)?
+                }
+            }
+        }
+
+        self.apply(&mut state, resolution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(id: &str, dependencies: &[&str]) -> Plan {
+        Plan {
+            pid: id.parse().unwrap(),
+            resolved: Resolved {
+                channel: String::from("stable"),
+                version: String::from("1.0"),
+                explicit: true,
+                dependencies: dependencies.iter().map(|dep| dep.parse().unwrap()).collect()
+            },
+            artifact: 0,
+            reused: Vec::new(),
+            libraries: Vec::new()
+        }
+    }
+
+    #[test]
+    fn skip_dependents() {
+        let plans = vec![
+            plan("com.ourteam:bot", &["com.ourteam:core"]),
+            plan("com.ourteam:core", &[]),
+            plan("com.ourteam:admin", &["com.ourteam:bot"]),
+            plan("net.mamoe:chat-command", &["net.mamoe:mirai-console"])
+        ];
+
+        let skipped = skipped(&plans, |plan| plan.pid.name == "core");
+
+        assert_eq!(vec![
+            "com.ourteam:admin (needs com.ourteam:bot)",
+            "com.ourteam:bot (needs com.ourteam:core)",
+            "com.ourteam:core"
+        ], skipped.into_values().collect::<Vec<_>>());
+    }
+}
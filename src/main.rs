@@ -3,46 +3,243 @@ mod index;
 mod logger;
 mod get;
 mod config;
+mod install;
+mod checksum;
+mod signature;
+mod resolve;
+mod query;
+mod graph;
+mod state;
+mod version;
+mod net;
+mod auth;
+mod metadata;
+mod pom;
+mod cache;
+mod progress;
+mod proxy;
+mod space;
 
 use std::iter::FromIterator;
+use std::sync::Arc;
 
-use clap::{App, Arg, SubCommand};
+use clap::{App, Arg, ArgMatches, SubCommand};
 use index::{PackageVersion, PackageEntry, Packages, Updater};
 use serde_json::Value;
 use config::Config;
+use index::PackageID;
 use logger::StdioLogger;
+use progress::{DownloadObserver, Phase};
+use graph::Graph;
+use query::{Query, Source};
+use resolve::Resolver;
+use signature::Keyring;
+use version::VersionReq;
+
+fn parse_pid(pid: &str) -> PackageID {
+    or_exit(pid.parse())
+}
+
+/**
+ * Print the error and exit, for errors that are the user's business rather than a bug.
+ */
+fn or_exit<T, E: std::fmt::Display>(result: std::result::Result<T, E>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1)
+        }
+    }
+}
+
+/**
+ * Report the end of a transaction to `observer`, which also reports the error, then exit on failure like [`or_exit`].
+ */
+fn finish<T>(observer: &Arc<dyn DownloadObserver>, result: anyhow::Result<T>) -> T {
+    match result {
+        Ok(value) => {
+            observer.phase(&Phase::Done);
+            value
+        }
+
+        Err(err) => {
+            observer.failed(&err.to_string());
+            std::process::exit(1)
+        }
+    }
+}
+
+/**
+ * Value of a global option, which may be given before or after the subcommand.
+ */
+fn global_value<'a>(matches: &'a ArgMatches, name: &str) -> Option<&'a str> {
+    matches.value_of(name).or_else(|| matches.subcommand().1.and_then(|sub| sub.value_of(name)))
+}
+
+/**
+ * The observer chosen by `--progress` and `--progress-fd`.
+ */
+fn observer(matches: &ArgMatches) -> Arc<dyn DownloadObserver> {
+    match global_value(matches, "progress") {
+        Some("json") => {
+            let fd = global_value(matches, "progress-fd").map(|fd| or_exit(fd.parse::<u32>().map_err(|err| format!("invalid --progress-fd {}: {}", fd, err))));
+
+            or_exit(progress::json(fd))
+        }
+
+        _ => progress::observer()
+    }
+}
 
 fn main() {
     let matches = App::new("mpt-get")
         .version("0.1.0")
+        .arg(Arg::with_name("progress")
+            .long("progress")
+            .global(true)
+            .takes_value(true)
+            .possible_values(&["auto", "json"])
+            .help("Report progress as bars or lines (auto), or as newline-delimited JSON events on stderr (json)"))
+        .arg(Arg::with_name("progress-fd")
+            .long("progress-fd")
+            .global(true)
+            .takes_value(true)
+            .help("Write JSON progress events to this file descriptor instead of stderr"))
+        .arg(Arg::with_name("limit-rate")
+            .long("limit-rate")
+            .global(true)
+            .takes_value(true)
+            .value_name("RATE")
+            .help("Limit the bandwidth of downloads and index fetches, such as 500K or 2M bytes per second"))
         .subcommand(SubCommand::with_name("update").about("Update index from remote server"))
         .subcommand(SubCommand::with_name("list").about("List all packages"))
+        .subcommand(SubCommand::with_name("clean").about("Remove every downloaded artifact from the cache"))
+        .subcommand(SubCommand::with_name("autoclean").about("Remove cached artifacts no installed package or indexed version refers to"))
         .subcommand(
             SubCommand::with_name("show")
                 .alias("info")
                 .about("Show package information")
                 .arg(Arg::with_name("PKG").required(true))
-                .arg(Arg::with_name("All").short("a").help("Print full information")),
+                .arg(Arg::with_name("All").short("a").help("Print full information"))
+                .arg(Arg::with_name("remote").long("remote").help("Also list versions published in the repository")),
         )
         .subcommand(
             SubCommand::with_name("install")
                 // .alias("get")
                 .about("Download target package")
                 .arg(Arg::with_name("PKG").help("Package ID").required(true).index(1))
-                .arg(Arg::with_name("VERSION").help("Optional. Install package of target version").index(2))
+                .arg(Arg::with_name("VERSION").help("Optional. Install package of target version, or 'latest' published in the repository").index(2))
+                .arg(Arg::with_name("force").long("force").help("Ignore holds and pins"))
+                .arg(Arg::with_name("insecure").long("insecure").help("Do not verify checksums of downloaded packages"))
+        )
+        .subcommand(
+            SubCommand::with_name("upgrade")
+                .about("Upgrade installed packages, except held ones")
+                .arg(Arg::with_name("insecure").long("insecure").help("Do not verify checksums of downloaded packages"))
+        )
+        .subcommand(
+            SubCommand::with_name("hold")
+                .about("Prevent package from being upgraded")
+                .arg(Arg::with_name("PKG").help("Package ID").required(true))
+        )
+        .subcommand(
+            SubCommand::with_name("unhold")
+                .about("Allow held package to be upgraded again")
+                .arg(Arg::with_name("PKG").help("Package ID").required(true))
+        )
+        .subcommand(
+            SubCommand::with_name("pin")
+                .about("Restrict package to versions matching a constraint, such as 0.3.* or >=1.0, <2.0")
+                .arg(Arg::with_name("PKG").help("Package ID").required(true).index(1))
+                .arg(Arg::with_name("VERSION").help("Version constraint").required(true).index(2))
+        )
+        .subcommand(
+            SubCommand::with_name("unpin")
+                .about("Remove the version constraint of package")
+                .arg(Arg::with_name("PKG").help("Package ID").required(true))
+        )
+        .subcommand(
+            SubCommand::with_name("depends")
+                .about("Show dependency tree of package")
+                .arg(Arg::with_name("PKG").help("Package ID").required(true).index(1))
+                .arg(Arg::with_name("VERSION").help("Optional. Version to inspect, newest by default").index(2))
+                .arg(Arg::with_name("installed").long("installed").help("Use installed versions"))
+        )
+        .subcommand(
+            SubCommand::with_name("rdepends")
+                .about("Show packages that depend on package")
+                .arg(Arg::with_name("PKG").help("Package ID").required(true))
+                .arg(Arg::with_name("installed").long("installed").help("Only installed packages"))
+        )
+        .subcommand(
+            SubCommand::with_name("why")
+                .about("Explain why an installed package is present")
+                .arg(Arg::with_name("PKG").help("Package ID").required(true))
+        )
+        .subcommand(
+            SubCommand::with_name("graph")
+                .about("Print resolved dependency graph of packages, or of installed packages if none is given")
+                .arg(Arg::with_name("PKG").help("Package ID").multiple(true))
+                .arg(Arg::with_name("format").long("format").takes_value(true).possible_values(&["dot", "json"]).default_value("dot"))
+        )
+        .subcommand(
+            SubCommand::with_name("key")
+                .about("Manage OpenPGP keys trusted to sign packages")
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Trust an armored public key")
+                        .arg(Arg::with_name("FILE").help("Armored public key file").required(true))
+                        .arg(Arg::with_name("scope")
+                            .long("scope")
+                            .takes_value(true)
+                            .multiple(true)
+                            .number_of_values(1)
+                            .help("Only trust the key for packages matching, such as net.mamoe:*. Defaults to every package"))
+                )
+                .subcommand(SubCommand::with_name("list").about("List trusted keys"))
+                .subcommand(
+                    SubCommand::with_name("remove")
+                        .about("Stop trusting a key")
+                        .arg(Arg::with_name("ID").help("Fingerprint or long key ID").required(true))
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("config")
+                .about("Inspect the configuration")
+                .subcommand(SubCommand::with_name("list").about("Print the effective configuration, secrets are redacted"))
         )
         .get_matches();
 
-    let config = Config::default();     // read from file;
-    let updater = config.updater::<StdioLogger>();
+    let mut config = or_exit(Config::load());
+
+    if let Some(rate) = global_value(&matches, "limit-rate") {
+        config.download.limit_rate = Some(or_exit(rate.parse()));
+    }
+
+    let observer = observer(&matches);
+    let updater = config.updater::<StdioLogger>().observer(observer.clone());
+    let installer = config.installer::<StdioLogger>().observer(observer.clone());
 
     match matches.subcommand() {
         ("update", _) => {
-            updater.update().unwrap();
+            finish(&observer, updater.update());
 
             println!("Done. Use mpt-get list to get all indexed packages.");
         }
 
+        ("clean", _) => {
+            let freed = or_exit(installer.clean());
+
+            println!("Removed {} from the cache.", freed);
+        }
+
+        ("autoclean", _) => {
+            let freed = or_exit(installer.autoclean());
+
+            println!("Removed {} from the cache.", freed);
+        }
+
         ("list", _) => {
             let pkgs = updater.index().unwrap();
             
@@ -131,18 +328,196 @@ fn main() {
 {0}channels: {4:?}
 {0}website: {5}"#, indent, pid, pkg.name, pkg.description, pkg.channels, pkg.website);
 
+            let repos = get::route(&config.source_repos(), &pid);
+
+            match repos.split_first() {
+                Some((repo, [])) => println!("{}repository: {}", indent, repo),
+                Some((repo, rest)) => println!("{}repository: {}, then {}", indent, repo, rest.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")),
+                None => println!("{}repository: none, check include and exclude of [[repositories]] in the config", indent)
+            }
+
+            if let Some(installed) = or_exit(installer.state()).packages.get(&pid) {
+                if !installed.libraries.is_empty() {
+                    let libraries: Vec<String> = installed.libraries.iter().map(|library| format!("{} {}", library.id, library.version)).collect();
+
+                    println!("{}libraries: {}", indent, libraries.join(", "));
+                }
+            }
+
             if let None = arg.index_of("All") {
                 println!("{}newest version: {}", indent, newest_version(vers))
             } else {
                 println!("{}all versions: ", indent);
                 print!("{}", all_versions(vers, &indent, &format!("{0}{0}", &indent)));
             }
+
+            if arg.is_present("remote") {
+                match or_exit(installer.remote_metadata(&pid)) {
+                    Some((repo, metadata)) => {
+                        println!("{}published in {}:", indent, repo);
+
+                        for version in metadata.versions.iter() {
+                            let newest = if Some(version) == metadata.newest() { " (newest)" } else { "" };
+
+                            println!("{0}{0}{1}{2}", indent, version, newest);
+                        }
+                    }
+
+                    None => println!("{}published versions: unknown, no repository publishes maven-metadata.xml", indent)
+                }
+            }
+        }
+
+        ("install", Some(arg)) => {
+            let pid = parse_pid(arg.value_of("PKG").expect("unreachable"));
+
+            let installer = installer.insecure(arg.is_present("insecure"));
+
+            finish(&observer, installer.install(&pid, arg.value_of("VERSION"), arg.is_present("force")));
+        }
+
+        ("upgrade", Some(arg)) => {
+            let installer = installer.insecure(arg.is_present("insecure"));
+
+            finish(&observer, installer.upgrade());
+        }
+
+        ("hold", Some(arg)) | ("unhold", Some(arg)) => {
+            let pid = parse_pid(arg.value_of("PKG").expect("unreachable"));
+            let mut state = or_exit(installer.state());
+
+            if matches.subcommand_name() == Some("hold") {
+                state.holds.insert(pid.clone());
+                println!("{} set on hold.", pid);
+            } else if state.holds.remove(&pid) {
+                println!("Canceled hold on {}.", pid);
+            } else {
+                println!("{} was not held.", pid);
+            }
+
+            or_exit(installer.save_state(&state));
+        }
+
+        ("pin", Some(arg)) => {
+            let pid = parse_pid(arg.value_of("PKG").expect("unreachable"));
+            let req: VersionReq = or_exit(arg.value_of("VERSION").expect("unreachable").parse());
+            let mut state = or_exit(installer.state());
+
+            if let Some(installed) = state.packages.get(&pid) {
+                if !req.matches_str(&installed.version) {
+                    println!("Warning: installed version {} does not match {}.", installed.version, req);
+                }
+            }
+
+            println!("{} pinned to {}.", pid, req);
+            state.pins.insert(pid, req);
+
+            or_exit(installer.save_state(&state));
+        }
+
+        ("unpin", Some(arg)) => {
+            let pid = parse_pid(arg.value_of("PKG").expect("unreachable"));
+            let mut state = or_exit(installer.state());
+
+            if state.pins.remove(&pid).is_some() {
+                println!("Removed pin of {}.", pid);
+            } else {
+                println!("{} was not pinned.", pid);
+            }
+
+            or_exit(installer.save_state(&state));
+        }
+
+        ("depends", Some(arg)) | ("rdepends", Some(arg)) | ("why", Some(arg)) => {
+            let pid = parse_pid(arg.value_of("PKG").expect("unreachable"));
+            let versions = or_exit(or_exit(updater.index()).versions(updater.index_dir()));
+            let state = or_exit(installer.state());
+            let source = if arg.is_present("installed") { Source::Installed(&state) } else { Source::Index };
+            let query = Query::new(&versions, source);
+
+            match matches.subcommand_name() {
+                Some("depends") => print!("{}", query.depends(&pid, arg.value_of("VERSION")).pretty_print()),
+
+                Some("rdepends") => {
+                    let found = query.rdepends(&pid);
+
+                    if found.is_empty() {
+                        println!("Nothing depends on {}.", pid);
+                    }
+
+                    for (by, version, req) in found {
+                        println!("{} {} needs {} {}", by, version, pid, req);
+                    }
+                }
+
+                _ => print!("{}", query.why(&state, &pid))
+            }
+        }
+
+        ("graph", Some(arg)) => {
+            let roots: Vec<PackageID> = arg.values_of("PKG").map(|pids| pids.map(parse_pid).collect()).unwrap_or_default();
+            let index = or_exit(updater.index());
+            let versions = or_exit(index.versions(updater.index_dir()));
+            let state = or_exit(installer.state());
+
+            let selection = if roots.is_empty() {
+                graph::selection_of_state(&state)
+            } else {
+                let requests: Vec<_> = roots.iter().map(|pid| (pid.clone(), None)).collect();
+
+                match Resolver::new(versions.clone(), &state).resolve(&requests, false) {
+                    Ok(resolution) => graph::selection_of_resolution(&resolution),
+                    Err(err) => {
+                        eprintln!("{}", err);
+
+                        let mut selection = graph::selection_of_state(&state);
+                        graph::select_newest(&versions, &mut selection, &roots);
+                        selection
+                    }
+                }
+            };
+
+            let graph = Graph::build(&versions, &state, &selection, &roots);
+
+            match arg.value_of("format") {
+                Some("json") => println!("{}", graph.to_json()),
+                _ => print!("{}", graph.to_dot())
+            }
         }
 
-        ("install", arg) => {
-            println!("JUST FOR TEST!");
+        ("key", Some(arg)) => {
+            let mut keyring = or_exit(Keyring::load(&config.keyring_path));
+
+            match arg.subcommand() {
+                ("add", Some(arg)) => {
+                    let armored = or_exit(std::fs::read_to_string(arg.value_of("FILE").expect("unreachable")));
+                    let scopes = arg.values_of("scope").map(|scopes| scopes.map(ToString::to_string).collect()).unwrap_or_else(|| vec![String::from("*")]);
+                    let fingerprint = or_exit(keyring.add(&armored, scopes));
+
+                    or_exit(keyring.save(&config.keyring_path));
+                    println!("Added key {}.", fingerprint);
+                }
+
+                ("remove", Some(arg)) => {
+                    let entry = or_exit(keyring.remove(arg.value_of("ID").expect("unreachable")));
 
-            // get::tests::test_downloading();
+                    or_exit(keyring.save(&config.keyring_path));
+                    println!("Removed key of {}.", entry.uid);
+                }
+
+                _ => {
+                    for (fingerprint, entry) in keyring.keys.iter() {
+                        println!("{}\n    uid: {}\n    trusted for: {}", fingerprint, entry.uid, entry.scopes.join(", "));
+                    }
+                }
+            }
+        }
+
+        ("config", Some(arg)) => {
+            match arg.subcommand() {
+                ("list", _) => print!("{}", or_exit(config.to_toml())),
+                _ => println!("{}", Config::path().display())
+            }
         }
 
         (name, arg) => panic!("Invalid command '{}'. Please use 'mpt-get --help' for more information.", name),
@@ -0,0 +1,187 @@
+use anyhow::Result;
+use roxmltree::{Document, Node};
+
+use crate::error::parse_err;
+
+/// A `<snapshotVersion>` entry, one per published file of a snapshot build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotVersion {
+    pub classifier: Option<String>,
+    pub extension: String,
+    pub value: String
+}
+
+/// `maven-metadata.xml` of an artifact, or of one snapshot version of it.
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    pub latest: Option<String>,
+    pub release: Option<String>,
+
+    /// Published versions, oldest first.
+    pub versions: Vec<String>,
+
+    /// `<timestamp>` and `<buildNumber>` of the newest snapshot build.
+    pub snapshot: Option<(String, String)>,
+    pub snapshot_versions: Vec<SnapshotVersion>
+}
+
+pub fn child<'a, 'i>(node: Node<'a, 'i>, name: &str) -> Option<Node<'a, 'i>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+pub fn text(node: Node, name: &str) -> Option<String> {
+    child(node, name)?.text().map(|text| text.trim().to_string()).filter(|text| !text.is_empty())
+}
+
+/**
+ * Split an artifact suffix such as `-all.jar` into its classifier and extension, `(Some("all"), "jar")`.
+ */
+pub fn split_suffix(suffix: &str) -> (Option<&str>, &str) {
+    match suffix.strip_prefix('-') {
+        Some(rest) => match rest.find('.') {
+            Some(dot) => (Some(&rest[..dot]), &rest[dot + 1..]),
+            None => (Some(rest), "jar")
+        },
+        None => (None, suffix.trim_start_matches('.'))
+    }
+}
+
+impl Metadata {
+    pub fn parse(xml: &str) -> Result<Metadata> {
+        let doc = Document::parse(xml)
+            .map_err(|err| parse_err(format!("invalid maven-metadata.xml: {}", err)))?;
+
+        let versioning = match child(doc.root_element(), "versioning") {
+            Some(versioning) => versioning,
+            None => return Ok(Metadata::default())
+        };
+
+        let versions = child(versioning, "versions")
+            .map(|versions| versions
+                .children()
+                .filter(|node| node.has_tag_name("version"))
+                .filter_map(|node| node.text().map(|text| text.trim().to_string()))
+                .collect())
+            .unwrap_or_default();
+
+        let snapshot = child(versioning, "snapshot")
+            .and_then(|snapshot| Some((text(snapshot, "timestamp")?, text(snapshot, "buildNumber")?)));
+
+        let snapshot_versions = child(versioning, "snapshotVersions")
+            .map(|versions| versions
+                .children()
+                .filter(|node| node.has_tag_name("snapshotVersion"))
+                .filter_map(|node| Some(SnapshotVersion {
+                    classifier: text(node, "classifier"),
+                    extension: text(node, "extension")?,
+                    value: text(node, "value")?
+                }))
+                .collect())
+            .unwrap_or_default();
+
+        Ok(Metadata {
+            latest: text(versioning, "latest"),
+            release: text(versioning, "release"),
+            versions,
+            snapshot,
+            snapshot_versions
+        })
+    }
+
+    /**
+     * The newest release, or the newest version if nothing is released.
+     */
+    pub fn newest(&self) -> Option<&String> {
+        self.release.as_ref()
+            .or(self.latest.as_ref())
+            .or_else(|| self.versions.last())
+    }
+
+    /**
+     * Timestamped version in file names of snapshot `version` with `suffix`, such as
+     * `2.5.0-20210301.120000-3` for `2.5.0-SNAPSHOT`.
+     */
+    pub fn snapshot_file_version(&self, version: &str, suffix: &str) -> Option<String> {
+        let (classifier, extension) = split_suffix(suffix);
+
+        let listed = self.snapshot_versions
+            .iter()
+            .find(|snapshot| snapshot.extension == extension && snapshot.classifier.as_deref() == classifier);
+
+        if let Some(snapshot) = listed {
+            return Some(snapshot.value.clone());
+        }
+
+        let (timestamp, build) = self.snapshot.as_ref()?;
+
+        Some(format!("{}-{}-{}", version.strip_suffix("-SNAPSHOT")?, timestamp, build))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METADATA: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<metadata>
+  <groupId>net.mamoe</groupId>
+  <artifactId>mirai-console</artifactId>
+  <versioning>
+    <latest>2.5.0-RC</latest>
+    <release>2.4.0</release>
+    <versions>
+      <version>2.3.2</version>
+      <version>2.4.0</version>
+      <version>2.5.0-RC</version>
+    </versions>
+    <lastUpdated>20210301120000</lastUpdated>
+  </versioning>
+</metadata>"#;
+
+    const SNAPSHOT: &str = r#"<metadata modelVersion="1.1.0">
+  <groupId>net.mamoe</groupId>
+  <artifactId>mirai-console</artifactId>
+  <version>2.5.0-SNAPSHOT</version>
+  <versioning>
+    <snapshot>
+      <timestamp>20210301.120000</timestamp>
+      <buildNumber>3</buildNumber>
+    </snapshot>
+    <snapshotVersions>
+      <snapshotVersion>
+        <classifier>all</classifier>
+        <extension>jar</extension>
+        <value>2.5.0-20210301.115500-2</value>
+      </snapshotVersion>
+      <snapshotVersion>
+        <extension>jar</extension>
+        <value>2.5.0-20210301.120000-3</value>
+      </snapshotVersion>
+    </snapshotVersions>
+  </versioning>
+</metadata>"#;
+
+    #[test]
+    fn versions() {
+        let metadata = Metadata::parse(METADATA).unwrap();
+
+        assert_eq!(vec!["2.3.2", "2.4.0", "2.5.0-RC"], metadata.versions);
+        assert_eq!(Some(&String::from("2.4.0")), metadata.newest());
+    }
+
+    #[test]
+    fn snapshot_versions() {
+        let metadata = Metadata::parse(SNAPSHOT).unwrap();
+
+        assert_eq!(Some(String::from("2.5.0-20210301.120000-3")), metadata.snapshot_file_version("2.5.0-SNAPSHOT", ".jar"));
+        assert_eq!(Some(String::from("2.5.0-20210301.115500-2")), metadata.snapshot_file_version("2.5.0-SNAPSHOT", "-all.jar"));
+        assert_eq!(Some(String::from("2.5.0-20210301.120000-3")), metadata.snapshot_file_version("2.5.0-SNAPSHOT", ".mirai.jar"));
+    }
+
+    #[test]
+    fn suffix() {
+        assert_eq!((Some("all"), "jar"), split_suffix("-all.jar"));
+        assert_eq!((None, "mirai.jar"), split_suffix(".mirai.jar"));
+        assert_eq!((None, "jar"), split_suffix(".jar"));
+    }
+}
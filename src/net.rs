@@ -0,0 +1,721 @@
+use std::{collections::{BTreeMap, HashMap}, convert::TryFrom, fmt, future::Future, io, net::{IpAddr, SocketAddr}, path::{Path, PathBuf}, pin::Pin, str::FromStr, sync::{Arc, Mutex}, task::{Context, Poll}, time::{Duration, Instant}};
+
+use anyhow::Result;
+use hyper::{body::{Bytes, HttpBody}, client::connect::dns::Name, header::{HeaderName, HeaderValue, AUTHORIZATION, LOCATION, PROXY_AUTHORIZATION, USER_AGENT}, service::Service, Body, Client, HeaderMap, Method, Request, Response, StatusCode, Uri};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_native_tls::native_tls::{Certificate, Identity, TlsConnector};
+
+use crate::error::{io_err, network_err, parse_err, transfer_err, Error, ErrorKind};
+use crate::proxy::{ProxyConnector, ProxyKind, ProxySettings};
+
+/// Redirects followed before giving up.
+pub const MAX_REDIRECTS: usize = 10;
+
+/// HTTP(S) client that follows redirects.
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: Client<ProxyConnector>,
+    proxies: Arc<ProxySettings>,
+    max_redirects: usize,
+    user_agent: HeaderValue,
+    read_timeout: Option<Duration>,
+    timeout: Option<Duration>
+}
+
+/// `[tls]` section of the config file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    /// PEM file with extra CA certificates, trusted in addition to the system roots.
+    pub ca_bundle: Option<PathBuf>,
+
+    /// PEM client certificate chain and its PKCS#8 private key, for repositories that require mutual TLS.
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+
+    /// Allow downloading artifacts over plain HTTP.
+    pub allow_http: bool
+}
+
+/// `[retry]` section of the config file.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Attempts per repository, including the first one.
+    pub attempts: u32,
+
+    /// Delay before the first retry in milliseconds, doubled after every attempt up to `max_delay`.
+    pub base_delay: u64,
+    pub max_delay: u64
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 3,
+            base_delay: 500,
+            max_delay: 10_000
+        }
+    }
+}
+
+impl RetryPolicy {
+    /**
+     * Delay before retry number `attempt` (starting at 1), with jitter so that clients
+     * which failed together do not all retry at once.
+     */
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = self.base_delay
+            .saturating_mul(1 << (attempt.max(1) - 1).min(16))
+            .min(self.max_delay);
+
+        Duration::from_millis(rand::thread_rng().gen_range(delay / 2..=delay))
+    }
+}
+
+/// `[network]` section of the config file, applied to every request made to Maven repositories.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// Seconds to connect, including the proxy handshake and TLS, 0 to wait forever.
+    pub connect_timeout: u64,
+
+    /// Seconds without receiving anything before a request is abandoned and retried, 0 to wait forever.
+    pub read_timeout: u64,
+
+    /// Seconds for a whole download attempt, 0 for no limit.
+    pub timeout: u64,
+
+    pub user_agent: String,
+
+    /// Addresses used instead of DNS, by host name.
+    pub resolve: BTreeMap<String, IpAddr>
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            connect_timeout: 30,
+            read_timeout: 60,
+            timeout: 0,
+            user_agent: format!("mpt-get/{}", env!("CARGO_PKG_VERSION")),
+            resolve: BTreeMap::new()
+        }
+    }
+}
+
+/**
+ * A timeout in seconds from the config file, `None` for 0.
+ */
+fn seconds(seconds: u64) -> Option<Duration> {
+    Some(Duration::from_secs(seconds)).filter(|_| seconds > 0)
+}
+
+/**
+ * Await `future` for at most `limit`, returns the limit that was exceeded.
+ */
+pub async fn deadline<F: Future>(limit: Option<Duration>, future: F) -> std::result::Result<F::Output, Duration> {
+    match limit {
+        Some(limit) => tokio::time::timeout(limit, future).await.map_err(|_| limit),
+        None => Ok(future.await)
+    }
+}
+
+/// Resolves host names with the `resolve` overrides of [`NetworkConfig`] before asking DNS.
+#[derive(Debug, Clone, Default)]
+pub struct Resolver(Arc<BTreeMap<String, IpAddr>>);
+
+impl Resolver {
+    pub fn new(overrides: &BTreeMap<String, IpAddr>) -> Resolver {
+        Resolver(Arc::new(overrides.iter().map(|(host, ip)| (host.to_ascii_lowercase(), *ip)).collect()))
+    }
+
+    pub async fn lookup(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        match self.0.get(&host.to_ascii_lowercase()) {
+            Some(ip) => Ok(vec![SocketAddr::new(*ip, port)]),
+            None => Ok(tokio::net::lookup_host((host, port)).await?.collect())
+        }
+    }
+}
+
+impl Service<Name> for Resolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<Self::Response>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let resolver = self.clone();
+
+        // the connector sets the port of the url
+        Box::pin(async move { resolver.lookup(name.as_str(), 0).await.map(Vec::into_iter) })
+    }
+}
+
+/// `[download]` section of the config file.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DownloadPolicy {
+    /// Downloads from the same host running at once.
+    pub max_connections_per_host: usize,
+
+    /// Bandwidth shared by every download and the index fetch.
+    pub limit_rate: Option<Rate>
+}
+
+impl Default for DownloadPolicy {
+    fn default() -> Self {
+        DownloadPolicy {
+            max_connections_per_host: 4,
+            limit_rate: None
+        }
+    }
+}
+
+/// Bytes per second, written like `500K` or `2M` with binary multiples, as curl's `--limit-rate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RateSpec", into = "String")]
+pub struct Rate(pub u64);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RateSpec {
+    Bytes(u64),
+    Text(String)
+}
+
+impl TryFrom<RateSpec> for Rate {
+    type Error = Error;
+
+    fn try_from(spec: RateSpec) -> std::result::Result<Self, Self::Error> {
+        match spec {
+            RateSpec::Bytes(bytes) => Rate::new(bytes),
+            RateSpec::Text(text) => text.parse()
+        }
+    }
+}
+
+impl Rate {
+    fn new(bytes: u64) -> std::result::Result<Rate, Error> {
+        if bytes == 0 {
+            return Err(parse_err("rate limit must be greater than 0"));
+        }
+
+        Ok(Rate(bytes))
+    }
+}
+
+impl FromStr for Rate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim();
+        let (number, unit) = match s.char_indices().last() {
+            Some((at, unit)) if unit.is_ascii_alphabetic() => (&s[..at], unit.to_ascii_uppercase()),
+            _ => (s, 'B')
+        };
+
+        let multiple: u64 = match unit {
+            'B' => 1,
+            'K' => 1024,
+            'M' => 1024 * 1024,
+            'G' => 1024 * 1024 * 1024,
+            _ => return Err(parse_err(format!("invalid rate {}, expected bytes per second such as 500K or 2M", s)))
+        };
+
+        let number: u64 = number.trim().parse()
+            .map_err(|_| parse_err(format!("invalid rate {}, expected bytes per second such as 500K or 2M", s)))?;
+
+        Rate::new(number.saturating_mul(multiple))
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            bytes if bytes % (1024 * 1024) == 0 => write!(f, "{}M", bytes / (1024 * 1024)),
+            bytes if bytes % 1024 == 0 => write!(f, "{}K", bytes / 1024),
+            bytes => write!(f, "{}", bytes)
+        }
+    }
+}
+
+impl From<Rate> for String {
+    fn from(rate: Rate) -> String {
+        rate.to_string()
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    rate: f64,
+
+    /// Bytes that may be received right away, negative when downloads are ahead of the rate.
+    tokens: f64,
+    refilled: Instant
+}
+
+/// Token bucket shared by downloads running at once, so that together they stay under a rate.
+/// Unlimited without a rate.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter(Option<Arc<Mutex<Bucket>>>);
+
+impl RateLimiter {
+    pub fn new(rate: Option<Rate>) -> RateLimiter {
+        RateLimiter(rate.map(|Rate(rate)| Arc::new(Mutex::new(Bucket {
+            rate: rate as f64,
+            tokens: 0.0,
+            refilled: Instant::now()
+        }))))
+    }
+
+    /**
+     * Take `bytes` out of the bucket, returns how long to wait until they are allowed.
+     * The bucket holds at most a second worth of bytes, so idle time does not allow a burst beyond that.
+     */
+    pub fn reserve(&self, bytes: usize) -> Duration {
+        let bucket = match &self.0 {
+            Some(bucket) => bucket,
+            None => return Duration::from_secs(0)
+        };
+
+        let mut bucket = bucket.lock().expect("lock poisoned");
+        let now = Instant::now();
+
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.refilled).as_secs_f64() * bucket.rate).min(bucket.rate);
+        bucket.refilled = now;
+        bucket.tokens -= bytes as f64;
+
+        if bucket.tokens < 0.0 {
+            Duration::from_secs_f64(-bucket.tokens / bucket.rate)
+        } else {
+            Duration::from_secs(0)
+        }
+    }
+
+    /**
+     * Wait until `bytes` received are allowed by the rate.
+     */
+    pub async fn consume(&self, bytes: usize) {
+        let wait = self.reserve(bytes);
+
+        if wait > Duration::from_secs(0) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Limits the connections to each host, shared by downloads running concurrently.
+#[derive(Debug, Clone)]
+pub struct HostLimiter {
+    per_host: usize,
+    hosts: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>
+}
+
+impl HostLimiter {
+    pub fn new(per_host: usize) -> HostLimiter {
+        HostLimiter {
+            per_host: per_host.max(1),
+            hosts: Arc::default()
+        }
+    }
+
+    /**
+     * Wait for a free connection to `host`, which is held until the permit is dropped.
+     */
+    pub async fn acquire(&self, host: &str) -> OwnedSemaphorePermit {
+        let semaphore = self.hosts
+            .lock()
+            .expect("lock poisoned")
+            .entry(host.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.per_host)))
+            .clone();
+
+        semaphore.acquire_owned().await.expect("semaphore is never closed")
+    }
+}
+
+impl Default for HostLimiter {
+    fn default() -> Self {
+        HostLimiter::new(DownloadPolicy::default().max_connections_per_host)
+    }
+}
+
+/**
+ * Whether `err` is a transient failure, such as a lost connection or a 5xx response.
+ */
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref::<Error>(), Some(err) if err.kind() == ErrorKind::Transfer)
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).map_err(|err| io_err(format!("cannot read {:?}: {}", path, err)).into())
+}
+
+/**
+ * Split a PEM bundle into its certificates.
+ */
+fn certificates(pem: &str) -> Vec<String> {
+    const END: &str = "-----END CERTIFICATE-----";
+
+    pem.split_inclusive(END)
+        .filter_map(|block| block.find("-----BEGIN CERTIFICATE-----").map(|begin| &block[begin..]))
+        .filter(|block| block.ends_with(END))
+        .map(String::from)
+        .collect()
+}
+
+impl TlsConfig {
+    fn connector(&self) -> Result<TlsConnector> {
+        let mut builder = TlsConnector::builder();
+
+        if let Some(path) = &self.ca_bundle {
+            let pem = String::from_utf8_lossy(&read(path)?).into_owned();
+            let certs = certificates(&pem);
+
+            if certs.is_empty() {
+                return Err(network_err(format!("no certificate found in CA bundle {:?}", path)).into());
+            }
+
+            for cert in certs {
+                let cert = Certificate::from_pem(cert.as_bytes())
+                    .map_err(|err| network_err(format!("invalid certificate in {:?}: {}", path, err)))?;
+
+                builder.add_root_certificate(cert);
+            }
+        }
+
+        match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => {
+                let identity = Identity::from_pkcs8(&read(cert)?, &read(key)?)
+                    .map_err(|err| network_err(format!("invalid client certificate {:?}: {}", cert, err)))?;
+
+                builder.identity(identity);
+            }
+
+            (None, None) => {}
+            _ => return Err(network_err("tls.client_cert and tls.client_key must be set together").into())
+        }
+
+        builder.build().map_err(|err| network_err(format!("failed to set up TLS: {}", err)).into())
+    }
+
+    /**
+     * Build an HTTP client that speaks both HTTP and HTTPS, with the timeouts, User-Agent and DNS overrides of `network`.
+     */
+    pub fn client(&self, proxies: &ProxySettings, network: &NetworkConfig) -> Result<HttpClient> {
+        let proxies = Arc::new(proxies.clone());
+        let connector = ProxyConnector::new(self.connector()?, proxies.clone(), Resolver::new(&network.resolve), seconds(network.connect_timeout));
+
+        Ok(HttpClient {
+            client: Client::builder().build(connector),
+            proxies,
+            max_redirects: MAX_REDIRECTS,
+            user_agent: HeaderValue::from_str(&network.user_agent)
+                .map_err(|_| parse_err(format!("invalid network.user_agent {:?}", network.user_agent)))?,
+            read_timeout: seconds(network.read_timeout),
+            timeout: seconds(network.timeout)
+        })
+    }
+
+    /**
+     * Refuse plain HTTP urls unless `allow_http` is set.
+     */
+    pub fn check(&self, uri: &Uri) -> Result<()> {
+        if uri.scheme_str() == Some("http") && !self.allow_http {
+            return Err(network_err(format!("refusing to download {} over plain HTTP, use an https:// repository or set tls.allow_http", uri)).into());
+        }
+
+        Ok(())
+    }
+}
+
+/**
+ * Resolve the `location` of a redirect from `from`. A redirect from HTTPS to HTTP is refused,
+ * and `Authorization` and other sensitive headers are removed from `headers` when the redirect leaves the host.
+ */
+fn follow(from: &Uri, location: &str, headers: &mut HeaderMap) -> Result<Uri> {
+    let scheme = from.scheme_str().unwrap_or("http");
+    let authority = from.authority().map(|auth| auth.as_str()).unwrap_or_default();
+
+    let target = if location.contains("://") {
+        location.to_string()
+    } else if location.starts_with("//") {
+        format!("{}:{}", scheme, location)
+    } else if location.starts_with('/') {
+        format!("{}://{}{}", scheme, authority, location)
+    } else {
+        let path = from.path();
+        let dir = &path[..path.rfind('/').map(|i| i + 1).unwrap_or(0)];
+
+        format!("{}://{}{}{}", scheme, authority, dir, location)
+    };
+
+    let to: Uri = target.parse()
+        .map_err(|_| network_err(format!("invalid redirect from {} to {}", from, location)))?;
+
+    if scheme == "https" && to.scheme_str() != Some("https") {
+        return Err(network_err(format!("refusing to follow redirect from {} to {}: it downgrades HTTPS", from, to)).into());
+    }
+
+    if from.host() != to.host() || from.port_u16() != to.port_u16() {
+        let sensitive: Vec<HeaderName> = headers.iter()
+            .filter(|(_, value)| value.is_sensitive())
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        headers.remove(AUTHORIZATION);
+
+        for name in sensitive {
+            headers.remove(name);
+        }
+    }
+
+    Ok(to)
+}
+
+impl HttpClient {
+    /**
+     * GET `uri` with `headers`, following redirects. Returns the final url with its response.
+     */
+    pub async fn send(&self, uri: Uri, headers: HeaderMap) -> Result<(Uri, Response<Body>)> {
+        self.request(Method::GET, uri, headers).await
+    }
+
+    /**
+     * HEAD `uri` with `headers`, following redirects.
+     */
+    pub async fn head(&self, uri: Uri, headers: HeaderMap) -> Result<(Uri, Response<Body>)> {
+        self.request(Method::HEAD, uri, headers).await
+    }
+
+    /**
+     * Next chunk of the body of `resp` from `url`, failing when nothing arrives within the read timeout.
+     */
+    pub async fn chunk(&self, url: &str, resp: &mut Response<Body>) -> Result<Option<Bytes>> {
+        deadline(self.read_timeout, resp.data()).await
+            .map_err(|limit| transfer_err(format!("{}: nothing received for {}s", url, limit.as_secs())))?
+            .transpose()
+            .map_err(|err| transfer_err(format!("{}: {}", url, err)).into())
+    }
+
+    /**
+     * The whole body of `resp` from `url`.
+     */
+    pub async fn body(&self, url: &str, mut resp: Response<Body>) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+
+        while let Some(data) = self.chunk(url, &mut resp).await? {
+            body.extend_from_slice(&data);
+        }
+
+        Ok(body)
+    }
+
+    /**
+     * Run one attempt of `what`, failing when it takes longer than the total timeout.
+     */
+    pub async fn attempt<T>(&self, what: &str, future: impl Future<Output = Result<T>>) -> Result<T> {
+        deadline(self.timeout, future).await
+            .map_err(|limit| transfer_err(format!("{} timed out after {}s", what, limit.as_secs())))?
+    }
+
+    async fn request(&self, method: Method, mut uri: Uri, mut headers: HeaderMap) -> Result<(Uri, Response<Body>)> {
+        if !headers.contains_key(USER_AGENT) {
+            headers.insert(USER_AGENT, self.user_agent.clone());
+        }
+
+        for _ in 0..=self.max_redirects {
+            let mut request = Request::builder().method(method.clone()).uri(uri.clone()).body(Body::empty())?;
+            *request.headers_mut() = headers.clone();
+
+            // plain HTTP goes through an HTTP proxy as is, HTTPS is tunneled with the credentials on CONNECT
+            if uri.scheme_str() == Some("http") {
+                if let Some(authorization) = self.proxies.for_uri(&uri)?.filter(|proxy| proxy.kind == ProxyKind::Http).and_then(|proxy| proxy.authorization()) {
+                    request.headers_mut().insert(PROXY_AUTHORIZATION, authorization);
+                }
+            }
+
+            let resp = deadline(self.read_timeout, self.client.request(request)).await
+                .map_err(|limit| transfer_err(format!("{}: no response within {}s", uri, limit.as_secs())))?
+                .map_err(|err| transfer_err(format!("{}: {}", uri, err)))?;
+
+            let redirected = matches!(resp.status(),
+                StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND | StatusCode::SEE_OTHER
+                | StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT);
+
+            if !redirected {
+                return Ok((uri, resp));
+            }
+
+            let location = resp.headers()
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .ok_or_else(|| network_err(format!("{} answered {} without a location", uri, resp.status())))?;
+
+            uri = follow(&uri, location, &mut headers)?;
+        }
+
+        Err(network_err(format!("too many redirects, gave up at {}", uri)).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn host_limit() {
+        let limiter = HostLimiter::new(2);
+        let _first = limiter.acquire("maven.aliyun.com").await;
+        let _second = limiter.acquire("maven.aliyun.com").await;
+
+        assert!(tokio::time::timeout(Duration::from_millis(50), limiter.acquire("maven.aliyun.com")).await.is_err());
+        assert!(tokio::time::timeout(Duration::from_millis(50), limiter.acquire("repo1.maven.org")).await.is_ok());
+    }
+
+    #[test]
+    fn rates() {
+        assert_eq!(Rate(500 * 1024), "500k".parse().unwrap());
+        assert_eq!(Rate(2 * 1024 * 1024), "2M".parse().unwrap());
+        assert_eq!(Rate(1000), "1000".parse().unwrap());
+        assert!("0".parse::<Rate>().is_err());
+        assert!("fast".parse::<Rate>().is_err());
+        assert!("2T".parse::<Rate>().is_err());
+
+        assert_eq!("500K", Rate(500 * 1024).to_string());
+        assert_eq!("1000", Rate(1000).to_string());
+
+        let policy: DownloadPolicy = toml::from_str("limit_rate = \"1M\"").unwrap();
+        assert_eq!(Some(Rate(1024 * 1024)), policy.limit_rate);
+
+        let policy: DownloadPolicy = toml::from_str("limit_rate = 4096").unwrap();
+        assert_eq!(Some(Rate(4096)), policy.limit_rate);
+    }
+
+    #[test]
+    fn rate_limit() {
+        let limiter = RateLimiter::new(Some(Rate(1000)));
+        let shared = limiter.clone();
+
+        // the bucket starts empty, so 500 bytes take half a second, and the next 500 bytes of another download another half
+        assert!((limiter.reserve(500).as_secs_f64() - 0.5).abs() < 0.05);
+        assert!((shared.reserve(500).as_secs_f64() - 1.0).abs() < 0.05);
+
+        assert_eq!(Duration::from_secs(0), RateLimiter::default().reserve(1 << 30));
+    }
+
+    #[test]
+    fn split_bundle() {
+        let pem = "# root\n-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n\n-----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----\n";
+        let certs = certificates(pem);
+
+        assert_eq!(2, certs.len());
+        assert!(certs[0].starts_with("-----BEGIN") && certs[1].contains("BBBB"));
+    }
+
+    #[test]
+    fn redirect_location() {
+        let from: Uri = "https://maven.aliyun.com/repository/public/a/b.jar".parse().unwrap();
+        let mut headers = HeaderMap::new();
+
+        assert_eq!("https://maven.aliyun.com/repository/public/a/c.jar", follow(&from, "c.jar", &mut headers).unwrap());
+        assert_eq!("https://maven.aliyun.com/a.jar", follow(&from, "/a.jar", &mut headers).unwrap());
+        assert_eq!("https://cdn.example.com/a.jar", follow(&from, "//cdn.example.com/a.jar", &mut headers).unwrap());
+        assert!(follow(&from, "http://maven.aliyun.com/a.jar", &mut headers).is_err());
+    }
+
+    #[test]
+    fn redirect_authorization() {
+        let from: Uri = "https://maven.example.com/a.jar".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, "Bearer secret".parse().unwrap());
+
+        follow(&from, "/b.jar", &mut headers).unwrap();
+        assert!(headers.contains_key(AUTHORIZATION));
+
+        let mut token = HeaderValue::from_static("secret");
+        token.set_sensitive(true);
+        headers.insert(AUTHORIZATION, "Bearer secret".parse().unwrap());
+        headers.insert("private-token", token);
+        headers.insert(USER_AGENT, HeaderValue::from_static("mpt-get"));
+
+        follow(&from, "https://cdn.example.com/b.jar", &mut headers).unwrap();
+        assert!(!headers.contains_key(AUTHORIZATION));
+        assert!(!headers.contains_key("private-token"));
+        assert!(headers.contains_key(USER_AGENT));
+    }
+
+    #[test]
+    fn network_config() {
+        let network: NetworkConfig = toml::from_str("read_timeout = 5\nresolve = { \"maven.ourteam.com\" = \"10.0.0.7\" }").unwrap();
+
+        assert_eq!((30, 5, 0), (network.connect_timeout, network.read_timeout, network.timeout));
+        assert!(network.user_agent.starts_with("mpt-get/"));
+        assert_eq!(Some(Duration::from_secs(5)), seconds(network.read_timeout));
+        assert_eq!(None, seconds(network.timeout));
+        assert!(toml::from_str::<NetworkConfig>("resolve = { \"maven.ourteam.com\" = \"nexus\" }").is_err());
+    }
+
+    #[tokio::test]
+    async fn stalled_body() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let (sender, received) = tokio::sync::oneshot::channel();
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 1024];
+            let read = stream.read(&mut request).await.unwrap();
+            sender.send(String::from_utf8_lossy(&request[..read]).into_owned()).unwrap();
+
+            // a third of the body, then nothing
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nmpt").await.unwrap();
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+
+        let network = NetworkConfig {
+            read_timeout: 1,
+            resolve: vec![(String::from("Maven.Ourteam.com"), IpAddr::from([127, 0, 0, 1]))].into_iter().collect(),
+            ..NetworkConfig::default()
+        };
+
+        let client = TlsConfig::default().client(&ProxySettings::default(), &network).unwrap();
+        let url = format!("http://maven.ourteam.com:{}/a.jar", port);
+        let (_, mut resp) = client.send(url.parse().unwrap(), HeaderMap::new()).await.unwrap();
+
+        assert!(received.await.unwrap().to_lowercase().contains(&format!("user-agent: mpt-get/{}", env!("CARGO_PKG_VERSION"))));
+        assert_eq!(&b"mpt"[..], &client.chunk(&url, &mut resp).await.unwrap().unwrap()[..]);
+
+        let err = client.chunk(&url, &mut resp).await.unwrap_err();
+        assert!(is_transient(&err), "{}", err);
+
+        server.abort();
+    }
+
+    #[test]
+    fn backoff() {
+        let policy = RetryPolicy::default();
+
+        assert!(policy.delay(1) <= Duration::from_millis(500));
+        assert!(policy.delay(3) >= Duration::from_millis(1000));
+        assert!(policy.delay(30) <= Duration::from_millis(10_000));
+    }
+
+    #[test]
+    fn plain_http() {
+        let uri: Uri = "http://maven.aliyun.com/repository/public".parse().unwrap();
+
+        assert!(TlsConfig::default().check(&uri).is_err());
+        assert!(TlsConfig { allow_http: true, ..TlsConfig::default() }.check(&uri).is_ok());
+        assert!(TlsConfig::default().check(&"https://maven.aliyun.com".parse().unwrap()).is_ok());
+    }
+}
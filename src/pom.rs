@@ -0,0 +1,685 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use anyhow::Result;
+use roxmltree::{Document, Node};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{parse_err, resolve_err, Error, ErrorKind};
+use crate::index::PackageID;
+use crate::metadata::{child, text};
+
+/// Parent POMs followed before assuming they form a cycle.
+const MAX_PARENTS: usize = 16;
+
+/// Rounds of `${...}` expansion, properties may refer to other properties.
+const MAX_INTERPOLATION: usize = 8;
+
+/// `[libraries]` section of the config file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LibraryPolicy {
+    /// Download the runtime dependencies declared in the POM of installed packages.
+    pub resolve: bool,
+
+    /// Libraries mirai-console already provides, such as `net.mamoe:mirai-*`. They are never downloaded.
+    pub provided: Vec<String>
+}
+
+impl Default for LibraryPolicy {
+    fn default() -> Self {
+        LibraryPolicy {
+            resolve: true,
+            provided: vec![
+                "net.mamoe:mirai-*",
+                "org.jetbrains.kotlin:kotlin-stdlib*",
+                "org.jetbrains.kotlin:kotlin-reflect",
+                "org.jetbrains.kotlinx:kotlinx-coroutines-*",
+                "org.jetbrains.kotlinx:kotlinx-serialization-*",
+                "org.jetbrains:annotations"
+            ].into_iter().map(String::from).collect()
+        }
+    }
+}
+
+/// A `<dependency>` of a POM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    pub group: String,
+    pub artifact: String,
+    pub version: Option<String>,
+    pub scope: Option<String>,
+
+    /// `<type>`, `jar` if absent.
+    pub kind: Option<String>,
+    pub classifier: Option<String>,
+    pub optional: bool,
+
+    /// `groupId` and `artifactId` of excluded transitive dependencies, either may be `*`.
+    pub exclusions: Vec<(String, String)>
+}
+
+/// Identity of a dependency, declarations with the same key override each other.
+type DependencyKey = (String, String, String, Option<String>);
+
+impl Dependency {
+    fn id(&self) -> PackageID {
+        PackageID { domain: self.group.clone(), name: self.artifact.clone() }
+    }
+
+    fn key(&self) -> DependencyKey {
+        (self.group.clone(), self.artifact.clone(), self.kind.clone().unwrap_or_else(|| String::from("jar")), self.classifier.clone())
+    }
+
+    /**
+     * Whether the dependency is on the classpath at runtime.
+     */
+    fn is_runtime(&self) -> bool {
+        matches!(self.scope.as_deref(), None | Some("compile") | Some("runtime")) && !self.optional
+    }
+
+    fn is_import(&self) -> bool {
+        self.scope.as_deref() == Some("import") && self.kind.as_deref() == Some("pom")
+    }
+
+    fn excluded_by(&self, exclusions: &[(String, String)]) -> bool {
+        exclusions.iter().any(|(group, artifact)| (group == "*" || group == &self.group) && (artifact == "*" || artifact == &self.artifact))
+    }
+
+    fn map(&self, f: impl Fn(&str) -> String) -> Dependency {
+        Dependency {
+            group: f(&self.group),
+            artifact: f(&self.artifact),
+            version: self.version.as_deref().map(&f),
+            scope: self.scope.as_deref().map(&f),
+            kind: self.kind.as_deref().map(&f),
+            classifier: self.classifier.as_deref().map(&f),
+            optional: self.optional,
+            exclusions: self.exclusions.iter().map(|(group, artifact)| (f(group), f(artifact))).collect()
+        }
+    }
+}
+
+/// A POM as written, before inheriting from its parents.
+#[derive(Debug, Clone, Default)]
+struct RawPom {
+    parent: Option<(PackageID, String)>,
+    group: Option<String>,
+    version: Option<String>,
+    properties: HashMap<String, String>,
+    management: Vec<Dependency>,
+    dependencies: Vec<Dependency>
+}
+
+fn dependencies(node: Node) -> Vec<Dependency> {
+    node.children()
+        .filter(|node| node.has_tag_name("dependency"))
+        .filter_map(|node| Some(Dependency {
+            group: text(node, "groupId")?,
+            artifact: text(node, "artifactId")?,
+            version: text(node, "version"),
+            scope: text(node, "scope"),
+            kind: text(node, "type"),
+            classifier: text(node, "classifier"),
+            optional: text(node, "optional").as_deref() == Some("true"),
+            exclusions: child(node, "exclusions")
+                .map(|exclusions| exclusions
+                    .children()
+                    .filter(|node| node.has_tag_name("exclusion"))
+                    .filter_map(|node| Some((text(node, "groupId")?, text(node, "artifactId")?)))
+                    .collect())
+                .unwrap_or_default()
+        }))
+        .collect()
+}
+
+impl RawPom {
+    fn parse(xml: &str) -> Result<RawPom> {
+        let doc = Document::parse(xml)
+            .map_err(|err| parse_err(format!("invalid POM: {}", err)))?;
+        let project = doc.root_element();
+
+        let parent = child(project, "parent")
+            .and_then(|parent| Some((
+                PackageID { domain: text(parent, "groupId")?, name: text(parent, "artifactId")? },
+                text(parent, "version")?
+            )));
+
+        let properties = child(project, "properties")
+            .map(|properties| properties
+                .children()
+                .filter(Node::is_element)
+                .map(|node| (node.tag_name().name().to_string(), node.text().unwrap_or("").trim().to_string()))
+                .collect())
+            .unwrap_or_default();
+
+        Ok(RawPom {
+            parent,
+            group: text(project, "groupId"),
+            version: text(project, "version"),
+            properties,
+            management: child(project, "dependencyManagement")
+                .and_then(|management| child(management, "dependencies"))
+                .map(dependencies)
+                .unwrap_or_default(),
+            dependencies: child(project, "dependencies").map(dependencies).unwrap_or_default()
+        })
+    }
+}
+
+/**
+ * Declarations of `base` overridden by those of `overrides` with the same key.
+ */
+fn merge(mut base: Vec<Dependency>, overrides: &[Dependency]) -> Vec<Dependency> {
+    for dep in overrides {
+        match base.iter_mut().find(|other| other.key() == dep.key()) {
+            Some(other) => *other = dep.clone(),
+            None => base.push(dep.clone())
+        }
+    }
+
+    base
+}
+
+/**
+ * Expand `${name}` in `value` with `properties`, unknown properties are left as they are.
+ */
+fn interpolate(value: &str, properties: &HashMap<String, String>) -> String {
+    let mut value = value.to_string();
+
+    for _ in 0..MAX_INTERPOLATION {
+        let mut expanded = String::new();
+        let mut rest = value.as_str();
+
+        while let Some(start) = rest.find("${") {
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => break
+            };
+
+            expanded.push_str(&rest[..start]);
+
+            match properties.get(&rest[start + 2..end]) {
+                Some(property) => expanded.push_str(property),
+                None => expanded.push_str(&rest[start..=end])
+            }
+
+            rest = &rest[end + 1..];
+        }
+
+        expanded.push_str(rest);
+
+        if expanded == value {
+            break;
+        }
+
+        value = expanded;
+    }
+
+    value
+}
+
+/**
+ * The version of a dependency, `[1.2.3]` pins a version and other ranges are not supported.
+ */
+fn exact_version(dep: &Dependency) -> Result<String> {
+    let version = dep.version.as_deref()
+        .ok_or_else(|| resolve_err(format!("no version of {}:{} is declared", dep.group, dep.artifact)))?;
+
+    if !version.starts_with('[') && !version.starts_with('(') {
+        return Ok(version.to_string());
+    }
+
+    match version.strip_prefix('[').and_then(|version| version.strip_suffix(']')) {
+        Some(pinned) if !pinned.contains(',') => Ok(pinned.trim().to_string()),
+        _ => Err(resolve_err(format!("version range {} of {}:{} is not supported", version, dep.group, dep.artifact)).into())
+    }
+}
+
+/**
+ * Whether `err` is about a POM this resolver cannot follow, such as a version range or an unpublished BOM,
+ * rather than about reading it.
+ */
+fn unresolvable(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref::<Error>(), Some(err) if err.kind() == ErrorKind::Resolve)
+}
+
+/// Effective POM, with parents merged, properties expanded and `dependencyManagement` applied.
+#[derive(Debug, Clone)]
+pub struct Pom {
+    pub management: Vec<Dependency>,
+    pub dependencies: Vec<Dependency>
+}
+
+/// A library jar needed at runtime.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Library {
+    pub id: PackageID,
+    pub version: String,
+
+    /// Suffix of the jar, such as `.jar` or `-linux.jar` for a classifier.
+    pub suffix: String
+}
+
+/// Resolves runtime libraries from POMs, which are read with `fetch(id, version)`.
+pub struct PomResolver<F> {
+    fetch: F,
+    raw: HashMap<(PackageID, String), Option<RawPom>>,
+    effective: HashMap<(PackageID, String), Option<Pom>>,
+    loading: HashSet<(PackageID, String)>,
+
+    /// Libraries skipped with their dependencies because their POMs cannot be followed.
+    pub warnings: Vec<String>
+}
+
+impl <F: FnMut(&PackageID, &str) -> Result<Option<String>>> PomResolver<F> {
+    pub fn new(fetch: F) -> Self {
+        PomResolver {
+            fetch,
+            raw: HashMap::new(),
+            effective: HashMap::new(),
+            loading: HashSet::new(),
+            warnings: Vec::new()
+        }
+    }
+
+    fn raw(&mut self, id: &PackageID, version: &str) -> Result<Option<RawPom>> {
+        let key = (id.clone(), version.to_string());
+
+        if let Some(raw) = self.raw.get(&key) {
+            return Ok(raw.clone());
+        }
+
+        let raw = (self.fetch)(id, version)?
+            .map(|xml| RawPom::parse(&xml).map_err(|err| parse_err(format!("POM of {} {}: {}", id, version, err))))
+            .transpose()?;
+
+        self.raw.insert(key, raw.clone());
+
+        Ok(raw)
+    }
+
+    /**
+     * Effective POM of `id` `version`, `None` if it is not published.
+     */
+    pub fn pom(&mut self, id: &PackageID, version: &str) -> Result<Option<Pom>> {
+        let key = (id.clone(), version.to_string());
+
+        if let Some(pom) = self.effective.get(&key) {
+            return Ok(pom.clone());
+        }
+
+        if !self.loading.insert(key.clone()) {
+            return Err(resolve_err(format!("POM of {} {} imports itself", id, version)).into());
+        }
+
+        let pom = self.load(id, version);
+
+        self.loading.remove(&key);
+
+        let pom = pom?;
+        self.effective.insert(key, pom.clone());
+
+        Ok(pom)
+    }
+
+    fn load(&mut self, id: &PackageID, version: &str) -> Result<Option<Pom>> {
+        let mut chain = match self.raw(id, version)? {
+            Some(raw) => vec![raw],
+            None => return Ok(None)
+        };
+
+        while let Some((parent, parent_version)) = chain.last().and_then(|raw| raw.parent.clone()) {
+            if chain.len() > MAX_PARENTS {
+                return Err(resolve_err(format!("POM of {} {} has more than {} parents, they may form a cycle", id, version, MAX_PARENTS)).into());
+            }
+
+            let raw = self.raw(&parent, &parent_version)?
+                .ok_or_else(|| resolve_err(format!("parent POM {} {} of {} {} is not published", parent, parent_version, id, version)))?;
+
+            chain.push(raw);
+        }
+
+        let mut properties = HashMap::new();
+        let mut management = Vec::new();
+        let mut dependencies = Vec::new();
+
+        for raw in chain.iter().rev() {
+            properties.extend(raw.properties.clone());
+            management = merge(management, &raw.management);
+            dependencies = merge(dependencies, &raw.dependencies);
+        }
+
+        let project = &chain[0];
+        let group = project.group.clone()
+            .or_else(|| project.parent.as_ref().map(|(parent, _)| parent.domain.clone()))
+            .unwrap_or_else(|| id.domain.clone());
+
+        for prefix in ["project.", "pom.", ""].iter() {
+            properties.insert(format!("{}groupId", prefix), group.clone());
+            properties.insert(format!("{}artifactId", prefix), id.name.clone());
+            properties.insert(format!("{}version", prefix), project.version.clone().unwrap_or_else(|| version.to_string()));
+        }
+
+        if let Some((parent, parent_version)) = &project.parent {
+            properties.insert(String::from("project.parent.groupId"), parent.domain.clone());
+            properties.insert(String::from("project.parent.artifactId"), parent.name.clone());
+            properties.insert(String::from("project.parent.version"), parent_version.clone());
+        }
+
+        let expand = |value: &str| interpolate(value, &properties);
+        let (imports, mut management): (Vec<_>, Vec<_>) = management.iter()
+            .map(|dep| dep.map(expand))
+            .partition(Dependency::is_import);
+
+        // declared entries take precedence over those of imported BOMs
+        for import in imports {
+            let bom_version = exact_version(&import)?;
+            let bom = self.pom(&import.id(), &bom_version)?
+                .ok_or_else(|| resolve_err(format!("BOM {} {} imported by {} {} is not published", import.id(), bom_version, id, version)))?;
+
+            for dep in bom.management {
+                if !management.iter().any(|other| other.key() == dep.key()) {
+                    management.push(dep);
+                }
+            }
+        }
+
+        let dependencies = dependencies.iter()
+            .map(|dep| {
+                let mut dep = dep.map(expand);
+
+                if let Some(managed) = management.iter().find(|managed| managed.key() == dep.key()) {
+                    dep.version = dep.version.or_else(|| managed.version.clone());
+                    dep.scope = dep.scope.or_else(|| managed.scope.clone());
+
+                    if dep.exclusions.is_empty() {
+                        dep.exclusions = managed.exclusions.clone();
+                    }
+                }
+
+                dep
+            })
+            .collect();
+
+        Ok(Some(Pom { management, dependencies }))
+    }
+
+    /**
+     * Runtime libraries of `id` `version`, transitively and in breadth-first order, so the nearest
+     * declaration of a library wins like in Maven. Libraries matching a `provided` pattern are
+     * skipped together with their own dependencies. `None` if no POM of `id` is published.
+     * A library whose POM cannot be followed is skipped with its dependencies and noted in `warnings`.
+     */
+    pub fn libraries(&mut self, id: &PackageID, version: &str, provided: &[String]) -> Result<Option<Vec<Library>>> {
+        let root = match self.pom(id, version) {
+            Ok(Some(root)) => root,
+            Ok(None) => return Ok(None),
+            Err(err) if unresolvable(&err) => {
+                self.warnings.push(format!("the libraries of {} {} are skipped: {}", id, version, err));
+                return Ok(Some(Vec::new()));
+            }
+
+            Err(err) => return Err(err)
+        };
+
+        let mut libraries = Vec::new();
+        let mut seen: HashSet<DependencyKey> = HashSet::new();
+        seen.insert((id.domain.clone(), id.name.clone(), String::from("jar"), None));
+
+        let mut queue = root.dependencies
+            .iter()
+            .filter(|dep| dep.is_runtime())
+            .map(|dep| (dep.clone(), false, dep.exclusions.clone()))
+            .collect::<VecDeque<_>>();
+
+        while let Some((mut dep, transitive, exclusions)) = queue.pop_front() {
+            if !seen.insert(dep.key()) || provided.iter().any(|pattern| dep.id().matches(pattern)) {
+                continue;
+            }
+
+            // the root's dependencyManagement also decides the versions of transitive dependencies
+            if transitive {
+                if let Some(managed) = root.management.iter().find(|managed| managed.key() == dep.key() && managed.version.is_some()) {
+                    dep.version = managed.version.clone();
+                }
+            }
+
+            let version = match exact_version(&dep) {
+                Ok(version) => version,
+                Err(err) => {
+                    self.warnings.push(format!("{} and its dependencies are skipped: {}", dep.id(), err));
+                    continue;
+                }
+            };
+
+            // a library without a POM has no dependencies, as Maven assumes
+            let pom = match self.pom(&dep.id(), &version) {
+                Ok(pom) => pom,
+                Err(err) if unresolvable(&err) => {
+                    self.warnings.push(format!("{} {} and its dependencies are skipped: {}", dep.id(), version, err));
+                    continue;
+                }
+
+                Err(err) => return Err(err)
+            };
+
+            if dep.kind.as_deref().unwrap_or("jar") != "pom" {
+                libraries.push(Library {
+                    id: dep.id(),
+                    version: version.clone(),
+                    suffix: match &dep.classifier {
+                        Some(classifier) => format!("-{}.jar", classifier),
+                        None => String::from(".jar")
+                    }
+                });
+            }
+
+            if let Some(pom) = pom {
+                for next in pom.dependencies.into_iter().filter(|next| next.is_runtime() && !next.excluded_by(&exclusions)) {
+                    let mut next_exclusions = exclusions.clone();
+                    next_exclusions.extend(next.exclusions.iter().cloned());
+
+                    queue.push_back((next, true, next_exclusions));
+                }
+            }
+        }
+
+        Ok(Some(libraries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pom(coordinates: &str, body: &str) -> (String, String) {
+        let mut parts = coordinates.split(':');
+        let (group, artifact, version) = (parts.next().unwrap(), parts.next().unwrap(), parts.next().unwrap());
+
+        let xml = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<project xmlns="http://maven.apache.org/POM/4.0.0">
+  <modelVersion>4.0.0</modelVersion>
+  <groupId>{}</groupId>
+  <artifactId>{}</artifactId>
+  <version>{}</version>
+  {}
+</project>"#, group, artifact, version, body);
+
+        (format!("{}:{}:{}", group, artifact, version), xml)
+    }
+
+    fn resolve(poms: Vec<(String, String)>, root: &str, provided: &[&str]) -> Result<Vec<String>> {
+        let poms: HashMap<String, String> = poms.into_iter().collect();
+        let mut resolver = PomResolver::new(|id: &PackageID, version: &str| Ok(poms.get(&format!("{}:{}", id, version)).cloned()));
+        let provided: Vec<String> = provided.iter().map(ToString::to_string).collect();
+        let (id, version) = root.rsplit_once(':').unwrap();
+
+        Ok(resolver.libraries(&id.parse()?, version, &provided)?
+            .unwrap()
+            .into_iter()
+            .map(|lib| format!("{}:{}{}", lib.id, lib.version, lib.suffix))
+            .collect())
+    }
+
+    #[test]
+    fn transitive() {
+        let poms = vec![
+            pom("com.ourteam:bot:1.0", r#"
+  <dependencies>
+    <dependency><groupId>net.mamoe</groupId><artifactId>mirai-core-api</artifactId><version>2.4.0</version></dependency>
+    <dependency><groupId>com.squareup.okhttp3</groupId><artifactId>okhttp</artifactId><version>4.9.0</version></dependency>
+    <dependency><groupId>org.xerial</groupId><artifactId>sqlite-jdbc</artifactId><version>3.34.0</version><scope>runtime</scope></dependency>
+    <dependency><groupId>junit</groupId><artifactId>junit</artifactId><version>4.13</version><scope>test</scope></dependency>
+    <dependency><groupId>com.google.guava</groupId><artifactId>guava</artifactId><version>30.0</version><optional>true</optional></dependency>
+  </dependencies>"#),
+            pom("com.squareup.okhttp3:okhttp:4.9.0", r#"
+  <dependencies>
+    <dependency><groupId>com.squareup.okio</groupId><artifactId>okio</artifactId><version>2.8.0</version></dependency>
+    <dependency><groupId>org.jetbrains.kotlin</groupId><artifactId>kotlin-stdlib</artifactId><version>1.4.10</version></dependency>
+  </dependencies>"#),
+            pom("com.squareup.okio:okio:2.8.0", r#"
+  <dependencies>
+    <dependency><groupId>com.squareup.okhttp3</groupId><artifactId>okhttp</artifactId><version>4.9.0</version></dependency>
+  </dependencies>"#),
+        ];
+
+        let libraries = resolve(poms, "com.ourteam:bot:1.0", &["net.mamoe:mirai-*", "org.jetbrains.kotlin:kotlin-stdlib*"]).unwrap();
+
+        assert_eq!(vec!["com.squareup.okhttp3:okhttp:4.9.0.jar", "org.xerial:sqlite-jdbc:3.34.0.jar", "com.squareup.okio:okio:2.8.0.jar"], libraries);
+    }
+
+    #[test]
+    fn parent_and_management() {
+        let poms = vec![
+            pom("com.ourteam:parent:1.0", r#"
+  <packaging>pom</packaging>
+  <properties><ktor.version>1.5.0</ktor.version></properties>
+  <dependencyManagement>
+    <dependencies>
+      <dependency><groupId>io.ktor</groupId><artifactId>ktor-client-core</artifactId><version>${ktor.version}</version></dependency>
+      <dependency><groupId>io.ktor</groupId><artifactId>ktor-http</artifactId><version>${ktor.version}</version></dependency>
+      <dependency><groupId>com.ourteam</groupId><artifactId>bom</artifactId><version>2.0</version><type>pom</type><scope>import</scope></dependency>
+    </dependencies>
+  </dependencyManagement>"#),
+            pom("com.ourteam:bom:2.0", r#"
+  <dependencyManagement>
+    <dependencies>
+      <dependency><groupId>com.ourteam</groupId><artifactId>util</artifactId><version>2.0.1</version></dependency>
+    </dependencies>
+  </dependencyManagement>"#),
+            pom("com.ourteam:bot:1.0", r#"
+  <parent><groupId>com.ourteam</groupId><artifactId>parent</artifactId><version>1.0</version></parent>
+  <dependencies>
+    <dependency><groupId>io.ktor</groupId><artifactId>ktor-client-core</artifactId></dependency>
+    <dependency><groupId>com.ourteam</groupId><artifactId>util</artifactId></dependency>
+    <dependency><groupId>com.ourteam</groupId><artifactId>native</artifactId><version>${project.version}</version><classifier>linux</classifier></dependency>
+  </dependencies>"#),
+            pom("io.ktor:ktor-client-core:1.5.0", r#"
+  <dependencies>
+    <dependency><groupId>io.ktor</groupId><artifactId>ktor-http</artifactId><version>1.4.0</version></dependency>
+  </dependencies>"#),
+        ];
+
+        let libraries = resolve(poms, "com.ourteam:bot:1.0", &[]).unwrap();
+
+        assert_eq!(vec![
+            "io.ktor:ktor-client-core:1.5.0.jar",
+            "com.ourteam:util:2.0.1.jar",
+            "com.ourteam:native:1.0-linux.jar",
+            "io.ktor:ktor-http:1.5.0.jar"
+        ], libraries);
+    }
+
+    #[test]
+    fn exclusions() {
+        let poms = vec![
+            pom("com.ourteam:bot:1.0", r#"
+  <dependencies>
+    <dependency>
+      <groupId>org.slf4j</groupId><artifactId>slf4j-simple</artifactId><version>1.7.30</version>
+      <exclusions><exclusion><groupId>org.slf4j</groupId><artifactId>*</artifactId></exclusion></exclusions>
+    </dependency>
+  </dependencies>"#),
+            pom("org.slf4j:slf4j-simple:1.7.30", r#"
+  <dependencies>
+    <dependency><groupId>org.slf4j</groupId><artifactId>slf4j-api</artifactId><version>1.7.30</version></dependency>
+    <dependency><groupId>org.fusesource.jansi</groupId><artifactId>jansi</artifactId><version>1.18</version></dependency>
+  </dependencies>"#),
+        ];
+
+        let libraries = resolve(poms, "com.ourteam:bot:1.0", &[]).unwrap();
+
+        assert_eq!(vec!["org.slf4j:slf4j-simple:1.7.30.jar", "org.fusesource.jansi:jansi:1.18.jar"], libraries);
+    }
+
+    #[test]
+    fn unresolvable_skipped() {
+        let poms: HashMap<String, String> = vec![
+            pom("com.ourteam:bot:1.0", r#"
+  <dependencies>
+    <dependency><groupId>com.google.code.gson</groupId><artifactId>gson</artifactId><version>[2.8,3.0)</version></dependency>
+    <dependency><groupId>com.ourteam</groupId><artifactId>client</artifactId><version>1.0</version></dependency>
+    <dependency><groupId>com.ourteam</groupId><artifactId>orphan</artifactId><version>1.0</version></dependency>
+    <dependency><groupId>com.ourteam</groupId><artifactId>looped</artifactId><version>1.0</version></dependency>
+    <dependency><groupId>com.ourteam</groupId><artifactId>platform</artifactId><version>1.0</version></dependency>
+    <dependency><groupId>org.xerial</groupId><artifactId>sqlite-jdbc</artifactId><version>3.34.0</version></dependency>
+  </dependencies>"#),
+            pom("com.ourteam:orphan:1.0", r#"
+  <parent><groupId>com.ourteam</groupId><artifactId>parent</artifactId><version>1.0</version></parent>"#),
+            pom("com.ourteam:looped:1.0", r#"
+  <parent><groupId>com.ourteam</groupId><artifactId>looped</artifactId><version>1.0</version></parent>"#),
+            pom("com.ourteam:platform:1.0", r#"
+  <dependencyManagement>
+    <dependencies>
+      <dependency><groupId>com.ourteam</groupId><artifactId>platform</artifactId><version>1.0</version><type>pom</type><scope>import</scope></dependency>
+    </dependencies>
+  </dependencyManagement>"#),
+            pom("com.ourteam:client:1.0", r#"
+  <dependencyManagement>
+    <dependencies>
+      <dependency><groupId>io.ktor</groupId><artifactId>ktor-bom</artifactId><version>1.5.0</version><type>pom</type><scope>import</scope></dependency>
+    </dependencies>
+  </dependencyManagement>
+  <dependencies>
+    <dependency><groupId>io.ktor</groupId><artifactId>ktor-client-core</artifactId></dependency>
+  </dependencies>"#),
+        ].into_iter().collect();
+
+        let mut resolver = PomResolver::new(|id: &PackageID, version: &str| Ok(poms.get(&format!("{}:{}", id, version)).cloned()));
+        let libraries: Vec<String> = resolver.libraries(&"com.ourteam:bot".parse().unwrap(), "1.0", &[]).unwrap().unwrap()
+            .into_iter()
+            .map(|lib| format!("{}:{}{}", lib.id, lib.version, lib.suffix))
+            .collect();
+
+        assert_eq!(vec!["org.xerial:sqlite-jdbc:3.34.0.jar"], libraries);
+        assert_eq!(5, resolver.warnings.len());
+        assert!(resolver.warnings[0].contains("version range [2.8,3.0)"), "{}", resolver.warnings[0]);
+        assert!(resolver.warnings[1].contains("BOM io.ktor:ktor-bom 1.5.0 imported by com.ourteam:client 1.0 is not published"), "{}", resolver.warnings[1]);
+        assert!(resolver.warnings[2].contains("parent POM com.ourteam:parent 1.0 of com.ourteam:orphan 1.0 is not published"), "{}", resolver.warnings[2]);
+        assert!(resolver.warnings[3].contains("parents, they may form a cycle"), "{}", resolver.warnings[3]);
+        assert!(resolver.warnings[4].contains("POM of com.ourteam:platform 1.0 imports itself"), "{}", resolver.warnings[4]);
+
+        // the root itself
+        let mut resolver = PomResolver::new(|id: &PackageID, version: &str| Ok(poms.get(&format!("{}:{}", id, version)).cloned()));
+        assert_eq!(Some(Vec::new()), resolver.libraries(&"com.ourteam:client".parse().unwrap(), "1.0", &[]).unwrap());
+        assert_eq!(1, resolver.warnings.len());
+    }
+
+    #[test]
+    fn versions() {
+        let dep = |version: &str| Dependency {
+            group: String::from("g"),
+            artifact: String::from("a"),
+            version: Some(version.to_string()),
+            scope: None,
+            kind: None,
+            classifier: None,
+            optional: false,
+            exclusions: Vec::new()
+        };
+
+        assert_eq!("1.2.3", exact_version(&dep("[1.2.3]")).unwrap());
+        assert!(exact_version(&dep("[1.0,2.0)")).is_err());
+        assert_eq!("1.0-b", interpolate("${a}-${b}", &vec![("a", "${c}"), ("c", "1.0"), ("b", "b")].into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()));
+    }
+}
@@ -0,0 +1,587 @@
+use std::{collections::BTreeMap, fmt::Debug, fs::OpenOptions, io::{stderr, stdout, IsTerminal, Write}, sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex}};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::error::io_err;
+
+/// Width of a progress bar in characters.
+const BAR_WIDTH: u64 = 20;
+
+/// Frames of the spinner drawn for downloads of unknown size.
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+/// Progress bars are redrawn at most this often.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A line is printed for each download this often when the output is not a terminal.
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Receives the progress of downloads, several of which may run at once.
+pub trait DownloadObserver: Debug + Send + Sync {
+    /**
+     * Download `id` of `name` started with `received` bytes already on disk, `size` is `None`
+     * when the server does not tell.
+     */
+    fn started(&self, id: usize, name: &str, received: u64, size: Option<u64>);
+
+    fn progressed(&self, id: usize, received: u64);
+
+    /**
+     * Download `id` ended, with the `error` it failed with if it did not complete.
+     */
+    fn finished(&self, id: usize, error: Option<&str>);
+
+    /**
+     * Print a message without breaking the progress display.
+     */
+    fn info(&self, message: &str) {
+        println!("{}", message);
+    }
+
+    fn warn(&self, message: &str) {
+        eprintln!("{}", message);
+    }
+
+    /**
+     * A transaction entered `phase`, only reported by observers that are read by programs.
+     */
+    fn phase(&self, _phase: &Phase<'_>) {}
+
+    /**
+     * A transaction failed with `error`.
+     */
+    fn failed(&self, error: &str) {
+        eprintln!("{}", error);
+    }
+}
+
+/// Steps of a transaction such as `install` or `update`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "phase", rename_all = "snake_case")]
+pub enum Phase<'a> {
+    /// Fetching the index from `url`.
+    Update { url: &'a str },
+    Resolve,
+    Download { artifacts: usize },
+    Verify { name: &'a str },
+    Install { package: String, version: &'a str },
+    Done,
+    Failed { error: String }
+}
+
+/**
+ * Progress bars on a terminal, plain lines otherwise.
+ */
+pub fn observer() -> Arc<dyn DownloadObserver> {
+    if stdout().is_terminal() {
+        Arc::new(TerminalObserver::default())
+    } else {
+        Arc::new(PlainObserver::default())
+    }
+}
+
+/**
+ * [`JsonObserver`] writing to the file descriptor `fd`, or to stderr.
+ */
+pub fn json(fd: Option<u32>) -> Result<Arc<dyn DownloadObserver>> {
+    let out: Box<dyn Write + Send> = match fd {
+        Some(2) | None => Box::new(stderr()),
+        Some(fd) => Box::new(OpenOptions::new().append(true).open(format!("/dev/fd/{}", fd))
+            .map_err(|err| io_err(format!("failed to open file descriptor {} for progress: {}", fd, err)))?)
+    };
+
+    Ok(Arc::new(JsonObserver::new(out)))
+}
+
+/// A download reported to an observer, which is told that it ended when this is dropped.
+pub struct Progress {
+    observer: Arc<dyn DownloadObserver>,
+    id: usize,
+
+    /// Reported when dropped, `None` once complete.
+    error: Option<String>
+}
+
+impl Progress {
+    pub fn start(observer: &Arc<dyn DownloadObserver>, name: &str, received: u64, size: Option<u64>) -> Progress {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+        observer.started(id, name, received, size);
+
+        Progress { observer: observer.clone(), id, error: Some(String::from("interrupted")) }
+    }
+
+    pub fn update(&self, received: u64) {
+        self.observer.progressed(self.id, received);
+    }
+
+    /**
+     * Mark the download as complete, it was interrupted if dropped without this or [`Progress::fail`].
+     */
+    pub fn complete(mut self) {
+        self.error = None;
+    }
+
+    pub fn fail(mut self, error: &str) {
+        self.error = Some(error.to_string());
+    }
+
+    /**
+     * Complete or fail the download with the outcome of the transfer, which is passed on.
+     */
+    pub fn finish<T, E: std::fmt::Display>(self, result: std::result::Result<T, E>) -> std::result::Result<T, E> {
+        match &result {
+            Ok(_) => self.complete(),
+            Err(err) => self.fail(&err.to_string())
+        }
+
+        result
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.observer.finished(self.id, self.error.as_deref());
+    }
+}
+
+/// State of a download in flight.
+#[derive(Debug)]
+struct Transfer {
+    name: String,
+    received: u64,
+    size: Option<u64>,
+
+    /// Bytes on disk when the download started, which do not count towards its throughput.
+    initial: u64,
+    since: Instant,
+    reported: Instant
+}
+
+impl Transfer {
+    fn new(name: &str, received: u64, size: Option<u64>) -> Transfer {
+        let now = Instant::now();
+
+        Transfer { name: name.to_string(), received, size, initial: received, since: now, reported: now }
+    }
+
+    /**
+     * Bytes per second since the download started.
+     */
+    fn rate(&self) -> Option<f64> {
+        let elapsed = self.since.elapsed().as_secs_f64();
+
+        if elapsed < 0.5 {
+            return None;
+        }
+
+        Some(self.received.saturating_sub(self.initial) as f64 / elapsed)
+    }
+
+    fn eta(&self) -> Option<Duration> {
+        match (self.size, self.rate()) {
+            (Some(size), Some(rate)) if rate > 0.0 => Some(Duration::from_secs_f64(size.saturating_sub(self.received) as f64 / rate)),
+            _ => None
+        }
+    }
+
+    fn line(&self, tick: usize) -> String {
+        let rate = self.rate().map(|rate| format!(" {}/s", bytes(rate as u64))).unwrap_or_default();
+
+        match self.size {
+            Some(size) => {
+                let eta = self.eta().map(|eta| format!(" ETA {}", clock(eta))).unwrap_or_default();
+
+                format!("{} {} {:>3}% {}/{}{}{}", self.name, bar(self.received, size), percent(self.received, size), bytes(self.received), bytes(size), rate, eta)
+            }
+
+            None => format!("{} {} {}{}", self.name, SPINNER[tick % SPINNER.len()], bytes(self.received), rate)
+        }
+    }
+
+    /**
+     * [`Transfer::line`] without the bar, for output that is not redrawn.
+     */
+    fn report(&self) -> String {
+        let rate = self.rate().map(|rate| format!(", {}/s", bytes(rate as u64))).unwrap_or_default();
+
+        match self.size {
+            Some(size) => format!("{}: {}% of {}{}", self.name, percent(self.received, size), bytes(size), rate),
+            None => format!("{}: {}{}", self.name, bytes(self.received), rate)
+        }
+    }
+
+    fn summary(&self, complete: bool) -> String {
+        if !complete {
+            return format!("{} failed after {}", self.name, bytes(self.received));
+        }
+
+        let rate = self.rate().map(|rate| format!(" ({}/s)", bytes(rate as u64))).unwrap_or_default();
+
+        format!("{} {} in {:.1}s{}", self.name, bytes(self.received), self.since.elapsed().as_secs_f64(), rate)
+    }
+}
+
+fn percent(received: u64, size: u64) -> u64 {
+    (received * 100).checked_div(size).map_or(100, |percent| percent.min(100))
+}
+
+/**
+ * `[>>>>>><<<<<<<<<<<<<<]`, full when more than `size` is received.
+ */
+fn bar(received: u64, size: u64) -> String {
+    let filled = (received * BAR_WIDTH).checked_div(size).map_or(BAR_WIDTH, |filled| filled.min(BAR_WIDTH));
+
+    format!("[{}{}]", ">".repeat(filled as usize), "<".repeat((BAR_WIDTH - filled) as usize))
+}
+
+pub fn bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+fn clock(duration: Duration) -> String {
+    let secs = duration.as_secs();
+
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Bars {
+    active: BTreeMap<usize, Transfer>,
+
+    /// Lines drawn last time, replaced by the next drawing.
+    drawn: usize,
+    last_draw: Option<Instant>,
+    tick: usize
+}
+
+impl Bars {
+    /**
+     * Replace the bars drawn last time with `lines`, which are printed once for good, and the bars of downloads in flight.
+     */
+    fn draw(&mut self, lines: &[String]) {
+        let mut out = stdout().lock();
+
+        if self.drawn > 0 {
+            let _ = write!(out, "\x1B[{}F", self.drawn);
+        }
+
+        let _ = write!(out, "\x1B[J");
+
+        for line in lines {
+            let _ = writeln!(out, "{}", line);
+        }
+
+        for transfer in self.active.values() {
+            let _ = writeln!(out, "{}", transfer.line(self.tick));
+        }
+
+        let _ = out.flush();
+
+        self.drawn = self.active.len();
+        self.last_draw = Some(Instant::now());
+        self.tick += 1;
+    }
+}
+
+/// One progress bar per download in flight, redrawn in place.
+#[derive(Debug, Default)]
+pub struct TerminalObserver(Mutex<Bars>);
+
+impl TerminalObserver {
+    fn bars(&self) -> std::sync::MutexGuard<'_, Bars> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl DownloadObserver for TerminalObserver {
+    fn started(&self, id: usize, name: &str, received: u64, size: Option<u64>) {
+        let mut bars = self.bars();
+
+        bars.active.insert(id, Transfer::new(name, received, size));
+        bars.draw(&[]);
+    }
+
+    fn progressed(&self, id: usize, received: u64) {
+        let mut bars = self.bars();
+
+        if let Some(transfer) = bars.active.get_mut(&id) {
+            transfer.received = received;
+        }
+
+        // Option::is_none_or needs Rust 1.82
+        #[allow(clippy::unnecessary_map_or)]
+        let due = bars.last_draw.map_or(true, |last| last.elapsed() >= REDRAW_INTERVAL);
+
+        if due {
+            bars.draw(&[]);
+        }
+    }
+
+    fn finished(&self, id: usize, error: Option<&str>) {
+        let mut bars = self.bars();
+
+        if let Some(transfer) = bars.active.remove(&id) {
+            bars.draw(&[transfer.summary(error.is_none())]);
+        }
+    }
+
+    fn info(&self, message: &str) {
+        self.bars().draw(&[message.to_string()]);
+    }
+
+    fn warn(&self, message: &str) {
+        let mut bars = self.bars();
+        let active = std::mem::take(&mut bars.active);
+
+        // clear the bars, so the message on stderr does not land among them
+        bars.draw(&[]);
+        eprintln!("{}", message);
+
+        bars.active = active;
+        bars.drawn = 0;
+        bars.draw(&[]);
+    }
+}
+
+/// A line when each download ends, and every [`REPORT_INTERVAL`] while it runs, for logs and pipes.
+#[derive(Debug, Default)]
+pub struct PlainObserver(Mutex<BTreeMap<usize, Transfer>>);
+
+impl PlainObserver {
+    fn transfers(&self) -> std::sync::MutexGuard<'_, BTreeMap<usize, Transfer>> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl DownloadObserver for PlainObserver {
+    fn started(&self, id: usize, name: &str, received: u64, size: Option<u64>) {
+        self.transfers().insert(id, Transfer::new(name, received, size));
+    }
+
+    fn progressed(&self, id: usize, received: u64) {
+        if let Some(transfer) = self.transfers().get_mut(&id) {
+            transfer.received = received;
+
+            if transfer.reported.elapsed() >= REPORT_INTERVAL {
+                transfer.reported = Instant::now();
+                println!("{}", transfer.report());
+            }
+        }
+    }
+
+    fn finished(&self, id: usize, error: Option<&str>) {
+        if let Some(transfer) = self.transfers().remove(&id) {
+            println!("{}", transfer.summary(error.is_none()));
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Emitted {
+    received: u64,
+    at: Instant
+}
+
+/// Newline-delimited JSON events for programs such as deployment dashboards, one object per line
+/// with an `event` of `start`, `progress`, `complete`, `error`, `message` or `phase`.
+pub struct JsonObserver {
+    out: Mutex<Box<dyn Write + Send>>,
+    transfers: Mutex<BTreeMap<usize, Emitted>>
+}
+
+impl Debug for JsonObserver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("JsonObserver")
+    }
+}
+
+impl JsonObserver {
+    pub fn new(out: Box<dyn Write + Send>) -> JsonObserver {
+        JsonObserver { out: Mutex::new(out), transfers: Mutex::new(BTreeMap::new()) }
+    }
+
+    fn transfers(&self) -> std::sync::MutexGuard<'_, BTreeMap<usize, Emitted>> {
+        self.transfers.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /**
+     * Write `fields` as an `event`, stamped with milliseconds since the epoch. Events that cannot be written are lost.
+     */
+    fn emit(&self, event: &str, fields: Value) {
+        let mut object = match fields {
+            Value::Object(object) => object,
+            _ => serde_json::Map::new()
+        };
+
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_millis() as u64).unwrap_or(0);
+
+        object.insert(String::from("event"), Value::from(event));
+        object.insert(String::from("time"), Value::from(time));
+
+        let mut out = self.out.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let _ = writeln!(out, "{}", Value::Object(object));
+        let _ = out.flush();
+    }
+}
+
+impl DownloadObserver for JsonObserver {
+    fn started(&self, id: usize, name: &str, received: u64, size: Option<u64>) {
+        self.transfers().insert(id, Emitted { received, at: Instant::now() });
+        self.emit("start", json!({ "id": id, "name": name, "received": received, "size": size }));
+    }
+
+    fn progressed(&self, id: usize, received: u64) {
+        let due = match self.transfers().get_mut(&id) {
+            Some(emitted) if emitted.at.elapsed() >= REDRAW_INTERVAL => {
+                *emitted = Emitted { received, at: Instant::now() };
+                true
+            }
+
+            Some(emitted) => {
+                emitted.received = received;
+                false
+            }
+
+            None => false
+        };
+
+        if due {
+            self.emit("progress", json!({ "id": id, "received": received }));
+        }
+    }
+
+    fn finished(&self, id: usize, error: Option<&str>) {
+        let received = self.transfers().remove(&id).map(|emitted| emitted.received);
+
+        match error {
+            None => self.emit("complete", json!({ "id": id, "received": received })),
+            Some(error) => self.emit("error", json!({ "id": id, "received": received, "error": error }))
+        }
+    }
+
+    fn info(&self, message: &str) {
+        self.emit("message", json!({ "level": "info", "message": message }));
+    }
+
+    fn warn(&self, message: &str) {
+        self.emit("message", json!({ "level": "warn", "message": message }));
+    }
+
+    fn phase(&self, phase: &Phase<'_>) {
+        self.emit("phase", serde_json::to_value(phase).unwrap_or(Value::Null));
+    }
+
+    fn failed(&self, error: &str) {
+        self.phase(&Phase::Failed { error: error.to_string() });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bars() {
+        assert_eq!("[>>>>>>>>>><<<<<<<<<<]", bar(512, 1024));
+        assert_eq!("[<<<<<<<<<<<<<<<<<<<<]", bar(0, 1024));
+
+        // the server may send more than it announced
+        assert_eq!("[>>>>>>>>>>>>>>>>>>>>]", bar(2048, 1024));
+        assert_eq!(100, percent(2048, 1024));
+        assert_eq!(100, percent(0, 0));
+    }
+
+    #[test]
+    fn units() {
+        assert_eq!("512 B", bytes(512));
+        assert_eq!("1.5 KiB", bytes(1536));
+        assert_eq!("2.0 MiB", bytes(2 * 1024 * 1024));
+        assert_eq!("00:42", clock(Duration::from_secs(42)));
+        assert_eq!("1:01:01", clock(Duration::from_secs(3661)));
+    }
+
+    #[test]
+    fn lines() {
+        let known = Transfer::new("chat-command-0.4.0.mirai.jar", 1024, Some(4096));
+        assert_eq!("chat-command-0.4.0.mirai.jar [>>>>><<<<<<<<<<<<<<<]  25% 1.0 KiB/4.0 KiB", known.line(0));
+
+        let unknown = Transfer::new("lib-a-1.0.jar", 100, None);
+        assert_eq!("lib-a-1.0.jar | 100 B", unknown.line(0));
+        assert_eq!("lib-a-1.0.jar / 100 B", unknown.line(1));
+        assert_eq!("chat-command-0.4.0.mirai.jar: 25% of 4.0 KiB", known.report());
+        assert_eq!("lib-a-1.0.jar failed after 100 B", unknown.summary(false));
+    }
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn json_events() {
+        let buffer = Buffer::default();
+        let observer: Arc<dyn DownloadObserver> = Arc::new(JsonObserver::new(Box::new(buffer.clone())));
+
+        observer.phase(&Phase::Download { artifacts: 2 });
+
+        let progress = Progress::start(&observer, "lib-a-1.0.jar", 0, Some(4096));
+        progress.update(1024);
+        progress.complete();
+
+        Progress::start(&observer, "lib-b-2.1.jar", 512, None).fail("connection reset");
+        observer.warn("Attempt 1/3 failed");
+        observer.failed("failed to download 1 of 2 artifacts");
+
+        let events: Vec<Value> = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        let kinds: Vec<&str> = events.iter().map(|event| event["event"].as_str().unwrap()).collect();
+        assert_eq!(vec!["phase", "start", "complete", "start", "error", "message", "phase"], kinds);
+
+        assert_eq!("download", events[0]["phase"]);
+        assert_eq!(2, events[0]["artifacts"]);
+        assert_eq!(4096, events[1]["size"]);
+        assert_eq!(events[1]["id"], events[2]["id"]);
+        assert_eq!(1024, events[2]["received"]);
+        assert_eq!(Value::Null, events[3]["size"]);
+        assert_eq!(512, events[4]["received"]);
+        assert_eq!("connection reset", events[4]["error"]);
+        assert_eq!("warn", events[5]["level"]);
+        assert_eq!(("failed", "failed to download 1 of 2 artifacts"), (events[6]["phase"].as_str().unwrap(), events[6]["error"].as_str().unwrap()));
+        assert!(events.iter().all(|event| event["time"].is_u64()));
+    }
+}
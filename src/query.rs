@@ -0,0 +1,290 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::index::{PackageID, PackageVersion};
+use crate::state::InstalledState;
+use crate::version::{Version, VersionReq};
+
+/// Where `depends`, `rdepends` and `why` read versions from.
+#[derive(Debug, Clone, Copy)]
+pub enum Source<'a> {
+    /// Newest versions in the index.
+    Index,
+    /// Installed versions only.
+    Installed(&'a InstalledState)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeState {
+    Resolved(String),
+    NotInIndex,
+    NotInstalled,
+    NoMatchingVersion,
+    /// Already on the path from the root, not expanded again.
+    Cycle(String)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyNode {
+    pub pid: PackageID,
+    pub req: Option<VersionReq>,
+    pub state: NodeState,
+    pub children: Vec<DependencyNode>
+}
+
+impl DependencyNode {
+    pub fn pretty_print(&self) -> String {
+        let mut out = String::new();
+        self.print_into(&mut out, 0);
+        out
+    }
+
+    fn print_into(&self, out: &mut String, depth: usize) {
+        let indent = "    ".repeat(depth);
+        let req = self.req.as_ref().map(|req| format!(" ({})", req)).unwrap_or_default();
+        let state = match &self.state {
+            NodeState::Resolved(version) => version.clone(),
+            NodeState::NotInIndex => String::from("[not in index]"),
+            NodeState::NotInstalled => String::from("[not installed]"),
+            NodeState::NoMatchingVersion => String::from("[no matching version]"),
+            NodeState::Cycle(version) => format!("{} [cycle]", version)
+        };
+
+        out.push_str(&format!("{}{} {}{}\n", indent, self.pid, state, req));
+
+        for child in self.children.iter() {
+            child.print_into(out, depth + 1);
+        }
+    }
+}
+
+/// Walks the dependency information of the index.
+#[derive(Debug)]
+pub struct Query<'a> {
+    versions: &'a HashMap<PackageID, PackageVersion>,
+    source: Source<'a>
+}
+
+impl <'a> Query<'a> {
+    pub fn new(versions: &'a HashMap<PackageID, PackageVersion>, source: Source<'a>) -> Query<'a> {
+        Query {
+            versions,
+            source
+        }
+    }
+
+    /**
+     * The version of `pid` the query looks at.
+     */
+    fn version_of(&self, pid: &PackageID, req: Option<&VersionReq>) -> NodeState {
+        match self.source {
+            Source::Installed(state) => match state.packages.get(pid) {
+                Some(pkg) => NodeState::Resolved(pkg.version.clone()),
+                None => NodeState::NotInstalled
+            },
+
+            Source::Index => match self.versions.get(pid) {
+                Some(vers) => vers
+                    .newest(|v| req.map(|req| req.matches_str(v)).unwrap_or(true))
+                    .map(|(_, version)| NodeState::Resolved(version.to_string()))
+                    .unwrap_or(NodeState::NoMatchingVersion),
+                None => NodeState::NotInIndex
+            }
+        }
+    }
+
+    /**
+     * Forward dependency tree of `pid`, at `version` or the version picked from the source.
+     */
+    pub fn depends(&self, pid: &PackageID, version: Option<&str>) -> DependencyNode {
+        let state = match version {
+            Some(version) => NodeState::Resolved(version.to_string()),
+            None => self.version_of(pid, None)
+        };
+
+        self.expand(pid.clone(), None, state, &mut Vec::new())
+    }
+
+    fn expand(&self, pid: PackageID, req: Option<VersionReq>, state: NodeState, path: &mut Vec<PackageID>) -> DependencyNode {
+        let version = match &state {
+            NodeState::Resolved(version) => version.clone(),
+            _ => return DependencyNode { pid, req, state, children: Vec::new() }
+        };
+
+        if path.contains(&pid) {
+            return DependencyNode { pid, req, state: NodeState::Cycle(version), children: Vec::new() };
+        }
+
+        let deps = self.versions.get(&pid).map(|vers| vers.dependencies_of(&version)).unwrap_or_default();
+
+        path.push(pid.clone());
+
+        let children = deps
+            .into_iter()
+            .map(|(dep, dep_req)| {
+                let state = self.version_of(&dep, Some(&dep_req));
+                self.expand(dep, Some(dep_req), state, path)
+            })
+            .collect();
+
+        path.pop();
+
+        DependencyNode { pid, req, state, children }
+    }
+
+    /**
+     * Packages that depend on `pid`, with the version that does and its constraint.
+     * For the index every version is considered, for installed packages only the installed one.
+     */
+    pub fn rdepends(&self, pid: &PackageID) -> Vec<(PackageID, String, VersionReq)> {
+        let mut found: Vec<(PackageID, String, VersionReq)> = self.versions
+            .iter()
+            .flat_map(|(id, vers)| {
+                vers.dependencies
+                    .iter()
+                    .filter_map(move |(version, deps)| Some((id.clone(), version.clone(), deps.get(pid)?.clone())))
+            })
+            .filter(|(id, version, _)| match self.source {
+                Source::Installed(state) => state.packages.get(id).map(|pkg| &pkg.version == version).unwrap_or(false),
+                Source::Index => true
+            })
+            .collect();
+
+        found.sort_by(|(lhs, lhs_ver, _), (rhs, rhs_ver, _)| {
+            lhs.cmp(rhs).then_with(|| match (lhs_ver.parse::<Version>(), rhs_ver.parse::<Version>()) {
+                (Ok(lhs), Ok(rhs)) => lhs.cmp(&rhs),
+                _ => lhs_ver.cmp(rhs_ver)
+            })
+        });
+
+        found
+    }
+
+    /**
+     * Explain why an installed package is present, walking up to explicitly installed packages.
+     */
+    pub fn why(&self, state: &InstalledState, pid: &PackageID) -> String {
+        let mut out = String::new();
+
+        match state.packages.get(pid) {
+            Some(_) => self.why_into(state, pid, 0, &mut BTreeSet::new(), &mut out),
+            None => out.push_str(&format!("{} is not installed.\n", pid))
+        }
+
+        out
+    }
+
+    fn why_into(&self, state: &InstalledState, pid: &PackageID, depth: usize, seen: &mut BTreeSet<PackageID>, out: &mut String) {
+        let indent = "    ".repeat(depth);
+        let pkg = &state.packages[pid];
+        let mut notes = Vec::new();
+
+        if state.is_held(pid) {
+            notes.push(String::from("held"));
+        }
+
+        if let Some(pin) = state.pin(pid) {
+            notes.push(format!("pinned to {}", pin));
+        }
+
+        let notes = if notes.is_empty() { String::new() } else { format!(" ({})", notes.join(", ")) };
+
+        if !pkg.auto {
+            out.push_str(&format!("{}{} {} was explicitly requested{}\n", indent, pid, pkg.version, notes));
+            return;
+        }
+
+        if !seen.insert(pid.clone()) {
+            out.push_str(&format!("{}{} {} [cycle]\n", indent, pid, pkg.version));
+            return;
+        }
+
+        let dependents = Query::new(self.versions, Source::Installed(state)).rdepends(pid);
+
+        if dependents.is_empty() {
+            out.push_str(&format!("{}{} {} was pulled in as a dependency, but nothing needs it anymore{}\n", indent, pid, pkg.version, notes));
+        } else {
+            out.push_str(&format!("{}{} {} was pulled in by{}:\n", indent, pid, pkg.version, notes));
+
+            for (by, version, req) in dependents {
+                out.push_str(&format!("{}    {} {} needs {} {}\n", indent, by, version, pid, req));
+                self.why_into(state, &by, depth + 2, seen, out);
+            }
+        }
+
+        seen.remove(pid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state::InstalledPackage;
+
+    use super::*;
+
+    fn pid(s: &str) -> PackageID {
+        s.parse().unwrap()
+    }
+
+    fn versions() -> HashMap<PackageID, PackageVersion> {
+        let json = |s: &str| PackageVersion::from_value(serde_json::from_str(s).unwrap()).unwrap();
+        let mut versions = HashMap::new();
+
+        versions.insert(pid("net.mamoe:mirai-console"), json(r#"{"channels": {"stable": ["2.4.0"]}}"#));
+        versions.insert(pid("net.mamoe:chat-command"), json(r#"{
+            "channels": {"stable": ["0.3.0"]},
+            "dependencies": {"0.3.0": {"net.mamoe:mirai-console": ">=2.0"}}
+        }"#));
+        versions.insert(pid("org.example:bot"), json(r#"{
+            "channels": {"stable": ["1.0"]},
+            "dependencies": {"1.0": {"net.mamoe:chat-command": "0.3.*"}}
+        }"#));
+
+        versions
+    }
+
+    fn state() -> InstalledState {
+        let mut state = InstalledState::default();
+
+        for (id, version, auto) in [("net.mamoe:mirai-console", "2.4.0", true), ("net.mamoe:chat-command", "0.3.0", true), ("org.example:bot", "1.0", false)].iter() {
+            state.packages.insert(pid(id), InstalledPackage {
+                version: version.to_string(),
+                channel: "stable".to_string(),
+                file: Default::default(),
+                auto: *auto,
+                libraries: Vec::new()
+            });
+        }
+
+        state
+    }
+
+    #[test]
+    fn depends_tree() {
+        let versions = versions();
+        let tree = Query::new(&versions, Source::Index).depends(&pid("org.example:bot"), None);
+
+        assert_eq!("org.example:bot 1.0
+    net.mamoe:chat-command 0.3.0 (0.3.*)
+        net.mamoe:mirai-console 2.4.0 (>=2.0)
+", tree.pretty_print());
+    }
+
+    #[test]
+    fn rdepends_installed() {
+        let versions = versions();
+        let state = state();
+        let found = Query::new(&versions, Source::Installed(&state)).rdepends(&pid("net.mamoe:mirai-console"));
+
+        assert_eq!(vec![(pid("net.mamoe:chat-command"), "0.3.0".to_string(), ">=2.0".parse().unwrap())], found);
+    }
+
+    #[test]
+    fn why_dependency() {
+        let versions = versions();
+        let state = state();
+        let why = Query::new(&versions, Source::Installed(&state)).why(&state, &pid("net.mamoe:mirai-console"));
+
+        assert!(why.contains("net.mamoe:chat-command 0.3.0 needs net.mamoe:mirai-console >=2.0"), "{}", why);
+        assert!(why.contains("org.example:bot 1.0 was explicitly requested"), "{}", why);
+    }
+}
@@ -0,0 +1,410 @@
+use std::{collections::{BTreeMap, HashMap}, fmt::Display, path::Path};
+
+use anyhow::Result;
+
+use crate::error::resolve_err;
+use crate::index::{PackageID, PackageVersion, Packages};
+use crate::state::InstalledState;
+use crate::version::{Version, VersionReq};
+
+/// Why a constraint is placed on a package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    Requested,
+    Installed,
+    Pinned,
+    Held,
+    Required(PackageID, String)
+}
+
+#[derive(Debug, Clone)]
+pub struct Constraint {
+    pub pid: PackageID,
+    pub req: VersionReq,
+    pub reason: Reason
+}
+
+impl Display for Constraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.reason {
+            Reason::Requested => write!(f, "{} {} was requested", self.pid, self.req),
+            Reason::Installed => write!(f, "{} is installed", self.pid),
+            Reason::Pinned => write!(f, "{} is pinned to {}", self.pid, self.req),
+            Reason::Held => write!(f, "{} is held at {}", self.pid, self.req),
+            Reason::Required(by, version) => write!(f, "{} {} needs {} {}", by, version, self.pid, self.req)
+        }
+    }
+}
+
+/// A package and version picked by the resolver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolved {
+    pub channel: String,
+    pub version: String,
+
+    /// Requested by the user rather than pulled in by another package.
+    pub explicit: bool,
+
+    /// Packages this version depends on.
+    pub dependencies: Vec<PackageID>
+}
+
+pub type Resolution = BTreeMap<PackageID, Resolved>;
+
+#[derive(Debug, Clone, Default)]
+struct Search {
+    constraints: BTreeMap<PackageID, Vec<Constraint>>,
+    selected: Resolution
+}
+
+/**
+ * Picks a consistent set of versions over the index, every constraint of every selected
+ * package must hold. Candidates are tried newest first and the search backtracks on conflict.
+ */
+#[derive(Debug)]
+pub struct Resolver<'a> {
+    versions: HashMap<PackageID, PackageVersion>,
+    state: &'a InstalledState,
+    forced: Vec<PackageID>
+}
+
+impl <'a> Resolver<'a> {
+    pub fn new(versions: HashMap<PackageID, PackageVersion>, state: &'a InstalledState) -> Resolver<'a> {
+        Resolver {
+            versions,
+            state,
+            forced: Vec::new()
+        }
+    }
+
+    /**
+     * Load versions of every package listed in the index.
+     */
+    pub fn from_index<P: AsRef<Path>>(index: &Packages, index_dir: P, state: &'a InstalledState) -> Result<Resolver<'a>> {
+        Ok(Resolver::new(index.versions(index_dir)?, state))
+    }
+
+    /**
+     * Ignore pins and holds of `pid`, those of other packages still apply.
+     */
+    pub fn force(mut self, pid: &PackageID) -> Self {
+        self.forced.push(pid.clone());
+        self
+    }
+
+    /**
+     * Resolve `requests` together with every installed package.
+     *
+     * Installed packages keep their version when possible, requested ones get the newest version
+     * allowed by the constraints. With `upgrade`, installed packages are treated as requested.
+     */
+    pub fn resolve(&self, requests: &[(PackageID, Option<VersionReq>)], upgrade: bool) -> Result<Resolution> {
+        let mut search = Search::default();
+        let any: VersionReq = "*".parse()?;
+
+        for (pid, req) in requests {
+            let req = req.clone().unwrap_or_else(|| any.clone());
+
+            self.constrain(&mut search, Constraint { pid: pid.clone(), req, reason: Reason::Requested });
+        }
+
+        for pid in self.state.packages.keys() {
+            let reason = if upgrade { Reason::Requested } else { Reason::Installed };
+
+            self.constrain(&mut search, Constraint { pid: pid.clone(), req: any.clone(), reason });
+        }
+
+        let mut selected = self.solve(search).map_err(resolve_err)?.selected;
+
+        for (pid, resolved) in selected.iter_mut() {
+            let requested = requests.iter().any(|(id, _)| id == pid);
+            let installed = self.state.packages.get(pid).map(|pkg| !pkg.auto).unwrap_or(false);
+
+            resolved.explicit = requested || installed;
+        }
+
+        Ok(selected)
+    }
+
+    fn constrain(&self, search: &mut Search, constraint: Constraint) {
+        let pid = constraint.pid.clone();
+
+        if !search.constraints.contains_key(&pid) && !self.forced.contains(&pid) {
+            let mut implied = Vec::new();
+
+            if let Some(pin) = self.state.pin(&pid) {
+                implied.push(Constraint { pid: pid.clone(), req: pin.clone(), reason: Reason::Pinned });
+            }
+
+            if let (true, Some(installed)) = (self.state.is_held(&pid), self.state.packages.get(&pid)) {
+                if let Ok(req) = installed.version.parse() {
+                    implied.push(Constraint { pid: pid.clone(), req, reason: Reason::Held });
+                }
+            }
+
+            search.constraints.insert(pid.clone(), implied);
+        }
+
+        search.constraints.entry(pid).or_default().push(constraint);
+    }
+
+    fn solve(&self, search: Search) -> std::result::Result<Search, String> {
+        let next = search.constraints.keys().find(|pid| !search.selected.contains_key(*pid)).cloned();
+        let pid = match next {
+            Some(pid) => pid,
+            None => return Ok(search)
+        };
+
+        let constraints = &search.constraints[&pid];
+        let vers = self.versions.get(&pid).ok_or_else(|| explain(constraints, &format!("{} is not in the index", pid)))?;
+        let installed = self.state.packages.get(&pid);
+        let prefer_installed = !constraints.iter().any(|c| c.reason == Reason::Requested);
+
+        let mut candidates: Vec<(&str, &str)> = vers
+            .candidates(installed.map(|pkg| pkg.channel.as_str()))
+            .into_iter()
+            .filter(|(_, version)| constraints.iter().all(|c| c.req.matches_str(version)))
+            .collect();
+
+        // a version installed from the repository ahead of the index stays a candidate
+        if let Some(installed) = installed {
+            let version = installed.version.as_str();
+
+            if vers.channel_of(version).is_none() && constraints.iter().all(|c| c.req.matches_str(version)) {
+                let newer = |other: &str| match (version.parse::<Version>(), other.parse::<Version>()) {
+                    (Ok(version), Ok(other)) => version > other,
+                    _ => false
+                };
+
+                let idx = candidates.iter().position(|(_, other)| newer(other)).unwrap_or(candidates.len());
+                candidates.insert(idx, (installed.channel.as_str(), version));
+            }
+        }
+
+        if let (true, Some(installed)) = (prefer_installed, installed) {
+            if let Some(idx) = candidates.iter().position(|(_, v)| *v == installed.version) {
+                let current = candidates.remove(idx);
+                candidates.insert(0, current);
+            }
+        }
+
+        let mut conflict = None;
+
+        for (channel, version) in candidates {
+            let mut next = search.clone();
+
+            let dependencies = vers.dependencies_of(version);
+
+            next.selected.insert(pid.clone(), Resolved {
+                channel: channel.to_string(),
+                version: version.to_string(),
+                explicit: false,
+                dependencies: dependencies.keys().cloned().collect()
+            });
+
+            let mut result = Ok(());
+
+            for (dep, req) in dependencies {
+                let constraint = Constraint { pid: dep.clone(), req, reason: Reason::Required(pid.clone(), version.to_string()) };
+
+                if let Some(selected) = next.selected.get(&dep) {
+                    if !constraint.req.matches_str(&selected.version) {
+                        let mut all = next.constraints[&dep].clone();
+                        all.push(constraint);
+
+                        result = Err(explain(&all, &format!("{} {} was selected", dep, selected.version)));
+                        break;
+                    }
+                }
+
+                self.constrain(&mut next, constraint);
+            }
+
+            match result.and_then(|_| self.solve(next)) {
+                Ok(solved) => return Ok(solved),
+                Err(err) => {
+                    conflict.get_or_insert(err);
+                }
+            }
+        }
+
+        Err(conflict.unwrap_or_else(|| explain(constraints, &format!("no matching version of {} exists", pid))))
+    }
+}
+
+/**
+ * Render constraints as "A 1.2 needs B >=2 but C 1.0 needs B 1.x", `fallback` is used
+ * when a single constraint cannot explain the conflict by itself.
+ */
+fn explain(constraints: &[Constraint], fallback: &str) -> String {
+    let reasons: Vec<String> = constraints
+        .iter()
+        .filter(|c| c.reason != Reason::Installed)
+        .map(ToString::to_string)
+        .collect();
+
+    match reasons.len() {
+        0 => fallback.to_string(),
+        1 => format!("{} but {}", reasons[0], fallback),
+        _ => reasons.join(" but ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state::InstalledPackage;
+
+    use super::*;
+
+    fn pid(s: &str) -> PackageID {
+        s.parse().unwrap()
+    }
+
+    fn package(versions: &[&str], deps: &[(&str, &[(&str, &str)])]) -> PackageVersion {
+        let mut channels = HashMap::new();
+        channels.insert("stable".to_string(), versions.iter().map(ToString::to_string).collect());
+
+        let dependencies = deps
+            .iter()
+            .map(|(version, deps)| {
+                let deps = deps.iter().map(|(id, req)| (pid(id), req.parse().unwrap())).collect();
+                (version.to_string(), deps)
+            })
+            .collect();
+
+        PackageVersion { channels, dependencies, checksums: HashMap::new(), artifact: None, artifacts: HashMap::new() }
+    }
+
+    fn index() -> HashMap<PackageID, PackageVersion> {
+        let mut index = HashMap::new();
+
+        index.insert(pid("net.mamoe:mirai-console"), package(&["1.0", "2.0", "2.4.0"], &[]));
+        index.insert(pid("net.mamoe:chat-command"), package(&["0.3.0", "0.4.0"], &[
+            ("0.3.0", &[("net.mamoe:mirai-console", ">=1.0")]),
+            ("0.4.0", &[("net.mamoe:mirai-console", ">=2.0")])
+        ]));
+        index.insert(pid("org.example:a"), package(&["1.2"], &[
+            ("1.2", &[("org.example:b", ">=2")])
+        ]));
+        index.insert(pid("org.example:b"), package(&["1.0", "2.0"], &[]));
+        index.insert(pid("org.example:bot"), package(&["1.0", "2.0"], &[
+            ("1.0", &[("net.mamoe:chat-command", ">=0.3")]),
+            ("2.0", &[("net.mamoe:chat-command", ">=0.4")])
+        ]));
+        index.insert(pid("org.example:c"), package(&["1.0"], &[
+            ("1.0", &[("org.example:b", "1.x")])
+        ]));
+
+        index
+    }
+
+    fn installed(state: &mut InstalledState, id: &str, version: &str) {
+        state.packages.insert(pid(id), InstalledPackage {
+            version: version.to_string(),
+            channel: "stable".to_string(),
+            file: Default::default(),
+            auto: false,
+            libraries: Vec::new()
+        });
+    }
+
+    #[test]
+    fn resolve_newest() {
+        let state = InstalledState::default();
+        let resolver = Resolver::new(index(), &state);
+        let resolution = resolver.resolve(&[(pid("net.mamoe:chat-command"), None)], false).unwrap();
+
+        assert_eq!("0.4.0", resolution[&pid("net.mamoe:chat-command")].version);
+        assert_eq!("2.4.0", resolution[&pid("net.mamoe:mirai-console")].version);
+        assert!(!resolution[&pid("net.mamoe:mirai-console")].explicit);
+    }
+
+    #[test]
+    fn keep_installed_console() {
+        let mut state = InstalledState::default();
+        installed(&mut state, "net.mamoe:mirai-console", "1.0");
+        state.holds.insert(pid("net.mamoe:mirai-console"));
+
+        let resolver = Resolver::new(index(), &state);
+        let resolution = resolver.resolve(&[(pid("net.mamoe:chat-command"), None)], false).unwrap();
+
+        assert_eq!("0.3.0", resolution[&pid("net.mamoe:chat-command")].version);
+        assert_eq!("1.0", resolution[&pid("net.mamoe:mirai-console")].version);
+    }
+
+    #[test]
+    fn held_not_upgraded() {
+        let mut state = InstalledState::default();
+        installed(&mut state, "net.mamoe:mirai-console", "1.0");
+        installed(&mut state, "net.mamoe:chat-command", "0.3.0");
+        state.holds.insert(pid("net.mamoe:mirai-console"));
+
+        let resolver = Resolver::new(index(), &state);
+        let resolution = resolver.resolve(&[(pid("net.mamoe:mirai-console"), None)], true).unwrap();
+
+        assert_eq!("1.0", resolution[&pid("net.mamoe:mirai-console")].version);
+        assert_eq!("0.3.0", resolution[&pid("net.mamoe:chat-command")].version);
+
+        let resolution = Resolver::new(index(), &state).force(&pid("net.mamoe:mirai-console")).resolve(&[], true).unwrap();
+
+        assert_eq!("2.4.0", resolution[&pid("net.mamoe:mirai-console")].version);
+    }
+
+    #[test]
+    fn forced_root_keeps_held_dependency() {
+        let mut state = InstalledState::default();
+        installed(&mut state, "net.mamoe:chat-command", "0.3.0");
+        installed(&mut state, "org.example:bot", "1.0");
+        state.holds.insert(pid("net.mamoe:chat-command"));
+        state.pins.insert(pid("org.example:bot"), "1.*".parse().unwrap());
+
+        let resolver = Resolver::new(index(), &state).force(&pid("org.example:bot"));
+        let err = resolver.resolve(&[(pid("org.example:bot"), Some("2.0".parse().unwrap()))], false).unwrap_err();
+
+        assert!(err.to_string().contains("net.mamoe:chat-command is held at 0.3.0"), "{}", err);
+
+        let resolution = resolver.resolve(&[(pid("org.example:bot"), None)], false).unwrap();
+
+        assert_eq!("1.0", resolution[&pid("org.example:bot")].version);
+        assert_eq!("0.3.0", resolution[&pid("net.mamoe:chat-command")].version);
+    }
+
+    #[test]
+    fn pinned_within_req() {
+        let mut state = InstalledState::default();
+        installed(&mut state, "net.mamoe:mirai-console", "1.0");
+        state.pins.insert(pid("net.mamoe:mirai-console"), "<2.4".parse().unwrap());
+
+        let resolver = Resolver::new(index(), &state);
+        let resolution = resolver.resolve(&[(pid("net.mamoe:chat-command"), None)], true).unwrap();
+
+        assert_eq!("2.0", resolution[&pid("net.mamoe:mirai-console")].version);
+        assert_eq!("0.4.0", resolution[&pid("net.mamoe:chat-command")].version);
+
+        let err = resolver.resolve(&[(pid("net.mamoe:mirai-console"), Some(">=2.4".parse().unwrap()))], false).unwrap_err();
+
+        assert!(err.to_string().contains("net.mamoe:mirai-console is pinned to <2.4"), "{}", err);
+    }
+
+    #[test]
+    fn keep_unindexed_version() {
+        let mut state = InstalledState::default();
+        installed(&mut state, "net.mamoe:mirai-console", "2.5.0");
+
+        let resolver = Resolver::new(index(), &state);
+        let resolution = resolver.resolve(&[], true).unwrap();
+
+        assert_eq!("2.5.0", resolution[&pid("net.mamoe:mirai-console")].version);
+    }
+
+    #[test]
+    fn explain_conflict() {
+        let mut state = InstalledState::default();
+        installed(&mut state, "org.example:c", "1.0");
+
+        let resolver = Resolver::new(index(), &state);
+        let err = resolver.resolve(&[(pid("org.example:a"), None)], false).unwrap_err();
+
+        assert!(err.to_string().contains("org.example:a 1.2 needs org.example:b >=2 but org.example:c 1.0 needs org.example:b 1.x"), "{}", err);
+    }
+}
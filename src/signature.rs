@@ -0,0 +1,233 @@
+use std::{collections::BTreeMap, fs::{self, File}, io::{BufReader, Cursor}, path::{Path, PathBuf}};
+
+use anyhow::Result;
+use pgp::{types::PublicKeyTrait, ArmorOptions, Deserializable, SignedPublicKey, StandaloneSignature};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{io_err, parse_err, signature_err};
+use crate::index::PackageID;
+
+/// What to do when a signature is missing or cannot be verified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Ignore,
+    Warn,
+    Refuse
+}
+
+/// `[signature]` section of the config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SignaturePolicy {
+    pub missing: Action,
+    pub invalid: Action
+}
+
+impl Default for SignaturePolicy {
+    fn default() -> Self {
+        SignaturePolicy {
+            missing: Action::Ignore,
+            invalid: Action::Ignore
+        }
+    }
+}
+
+impl SignaturePolicy {
+    pub fn enabled(&self) -> bool {
+        self.missing != Action::Ignore || self.invalid != Action::Ignore
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyEntry {
+    pub uid: String,
+
+    /// Package patterns this key is trusted for, such as `net.mamoe:*`.
+    pub scopes: Vec<String>,
+    pub armored: String
+}
+
+/// Trusted OpenPGP keys, stored as `keyring.json` by fingerprint.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Keyring {
+    #[serde(default)]
+    pub keys: BTreeMap<String, KeyEntry>
+}
+
+/// Outcome of checking a detached signature.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Good { fingerprint: String, uid: String },
+    Missing,
+    Invalid(String)
+}
+
+pub fn parse_key(armored: &str) -> Result<SignedPublicKey> {
+    let (key, _) = SignedPublicKey::from_armor_single(Cursor::new(armored.as_bytes()))
+        .map_err(|err| parse_err(format!("invalid OpenPGP public key: {}", err)))?;
+
+    key.verify().map_err(|err| parse_err(format!("invalid OpenPGP public key: {}", err)))?;
+
+    Ok(key)
+}
+
+fn fingerprint(key: &SignedPublicKey) -> String {
+    key.fingerprint().as_bytes().iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+impl Keyring {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Keyring> {
+        let path = path.as_ref();
+
+        if !path.exists() {
+            return Ok(Keyring::default());
+        }
+
+        let file = File::open(path)
+            .map_err(|_| io_err(format!("cannot open {:?}", path)))?;
+
+        let keyring = serde_json::from_reader(BufReader::new(file))
+            .map_err(|err| io_err(format!("failed to parse {:?}: {}", path, err)))?;
+
+        Ok(keyring)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut tmp = PathBuf::from(path);
+        tmp.set_extension("json.tmp");
+
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, path)?;
+
+        Ok(())
+    }
+
+    /**
+     * Trust an armored public key for `scopes`, returns its fingerprint.
+     */
+    pub fn add(&mut self, armored: &str, scopes: Vec<String>) -> Result<String> {
+        let key = parse_key(armored)?;
+        let fingerprint = fingerprint(&key);
+        let uid = key.details.users
+            .first()
+            .map(|user| user.id.id().to_string())
+            .unwrap_or_default();
+
+        let armored = key.to_armored_string(ArmorOptions::default())
+            .map_err(|err| parse_err(format!("failed to armor key: {}", err)))?;
+
+        self.keys.insert(fingerprint.clone(), KeyEntry { uid, scopes, armored });
+
+        Ok(fingerprint)
+    }
+
+    /**
+     * Find a key by its fingerprint or a suffix of it, such as the long key ID.
+     */
+    pub fn find(&self, id: &str) -> Result<(&String, &KeyEntry)> {
+        let id = id.replace(' ', "").to_uppercase();
+        let found: Vec<(&String, &KeyEntry)> = self.keys.iter().filter(|(fp, _)| !id.is_empty() && fp.ends_with(&id)).collect();
+
+        match found.as_slice() {
+            [key] => Ok(*key),
+            [] => Err(signature_err(format!("no key matches {}", id)).into()),
+            _ => Err(signature_err(format!("{} matches more than one key, use the full fingerprint", id)).into())
+        }
+    }
+
+    pub fn remove(&mut self, id: &str) -> Result<KeyEntry> {
+        let fingerprint = self.find(id)?.0.clone();
+
+        Ok(self.keys.remove(&fingerprint).expect("unreachable"))
+    }
+
+    /**
+     * Check the detached `signature` of `data` with every key trusted for `pid`.
+     */
+    pub fn verify(&self, pid: &PackageID, data: &[u8], signature: Option<&str>) -> Verdict {
+        let signature = match signature {
+            Some(signature) => signature,
+            None => return Verdict::Missing
+        };
+
+        let signature = match StandaloneSignature::from_armor_single(Cursor::new(signature.as_bytes())) {
+            Ok((signature, _)) => signature,
+            Err(err) => return Verdict::Invalid(format!("malformed signature: {}", err))
+        };
+
+        let trusted = self.keys
+            .iter()
+            .filter(|(_, entry)| entry.scopes.iter().any(|scope| pid.matches(scope)));
+
+        for (fp, entry) in trusted {
+            let key = match parse_key(&entry.armored) {
+                Ok(key) => key,
+                Err(_) => continue
+            };
+
+            let good = signature.verify(&key, data).is_ok()
+                || key.public_subkeys.iter().any(|sub| signature.verify(sub, data).is_ok());
+
+            if good {
+                return Verdict::Good { fingerprint: fp.clone(), uid: entry.uid.clone() };
+            }
+        }
+
+        Verdict::Invalid(format!("not signed by any key trusted for {}", pid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // generated with `gpg --quick-gen-key "Mamoe <m@example.com>" ed25519 sign never`
+    const PUBLIC_KEY: &str = include_str!("../tests/data/mamoe.asc");
+    const DATA: &[u8] = b"hello\n";
+    const SIGNATURE: &str = include_str!("../tests/data/hello.txt.asc");
+
+    fn keyring(scope: &str) -> Keyring {
+        let mut keyring = Keyring::default();
+        keyring.add(PUBLIC_KEY, vec![scope.to_string()]).unwrap();
+        keyring
+    }
+
+    #[test]
+    fn good_signature() {
+        let pid: PackageID = "net.mamoe:chat-command".parse().unwrap();
+        let verdict = keyring("net.mamoe:*").verify(&pid, DATA, Some(SIGNATURE));
+
+        assert!(matches!(verdict, Verdict::Good { .. }), "{:?}", verdict);
+    }
+
+    #[test]
+    fn scoped_trust() {
+        let pid: PackageID = "com.ourteam:bot".parse().unwrap();
+
+        assert!(matches!(keyring("net.mamoe:*").verify(&pid, DATA, Some(SIGNATURE)), Verdict::Invalid(_)));
+        assert_eq!(Verdict::Missing, keyring("*").verify(&pid, DATA, None));
+    }
+
+    #[test]
+    fn tampered_data() {
+        let pid: PackageID = "net.mamoe:chat-command".parse().unwrap();
+
+        assert!(matches!(keyring("*").verify(&pid, b"hello!\n", Some(SIGNATURE)), Verdict::Invalid(_)));
+    }
+
+    #[test]
+    fn remove_by_key_id() {
+        let mut keyring = keyring("*");
+        let fingerprint = keyring.keys.keys().next().unwrap().clone();
+
+        keyring.remove(&fingerprint[fingerprint.len() - 16..].to_lowercase()).unwrap();
+        assert!(keyring.keys.is_empty());
+    }
+}
@@ -0,0 +1,136 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::error::install_err;
+use crate::progress;
+
+/// A filesystem, identified by its device, with the bytes available to unprivileged users.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Filesystem {
+    pub device: u64,
+    pub available: u64
+}
+
+/**
+ * The filesystem of `path`, or of its nearest existing parent. `None` if it cannot be told on this platform.
+ */
+#[cfg(unix)]
+pub fn filesystem(path: &Path) -> Option<Filesystem> {
+    use std::{ffi::CString, os::unix::{ffi::OsStrExt, fs::MetadataExt}};
+
+    let existing = path.ancestors().find(|dir| dir.exists())?;
+    let device = std::fs::metadata(existing).ok()?.dev();
+    let c_path = CString::new(existing.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+
+    // the field types differ between platforms
+    #[allow(clippy::unnecessary_cast)]
+    let available = (stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64);
+
+    Some(Filesystem { device, available })
+}
+
+#[cfg(not(unix))]
+pub fn filesystem(_: &Path) -> Option<Filesystem> {
+    None
+}
+
+/// Bytes a transaction is going to write, by directory.
+#[derive(Debug, Default)]
+pub struct SpaceNeeds {
+    needs: Vec<(PathBuf, u64)>,
+
+    /// Artifacts whose size is not known in advance.
+    pub unknown: usize
+}
+
+impl SpaceNeeds {
+    pub fn add(&mut self, dir: &Path, bytes: u64) {
+        match self.needs.iter_mut().find(|(other, _)| other == dir) {
+            Some((_, total)) => *total += bytes,
+            None => self.needs.push((dir.to_path_buf(), bytes))
+        }
+    }
+
+    /**
+     * Filesystems without room for what is written to them, with their directories, the bytes needed and the bytes available.
+     * Directories on the same filesystem add up.
+     */
+    pub fn shortages(&self, filesystem: impl Fn(&Path) -> Option<Filesystem>) -> Vec<(Vec<&Path>, u64, u64)> {
+        let mut filesystems: Vec<(Filesystem, Vec<&Path>, u64)> = Vec::new();
+
+        for (dir, bytes) in &self.needs {
+            let fs = match filesystem(dir) {
+                Some(fs) => fs,
+                None => continue
+            };
+
+            match filesystems.iter_mut().find(|(other, _, _)| other.device == fs.device) {
+                Some((_, dirs, total)) => {
+                    dirs.push(dir);
+                    *total += bytes;
+                }
+
+                None => filesystems.push((fs, vec![dir], *bytes))
+            }
+        }
+
+        filesystems.into_iter()
+            .filter(|(fs, _, needed)| *needed > fs.available)
+            .map(|(fs, dirs, needed)| (dirs, needed, fs.available))
+            .collect()
+    }
+
+    /**
+     * Fail with every shortage when a filesystem does not have room for what is written to it.
+     */
+    pub fn check(&self) -> Result<()> {
+        let shortages: Vec<String> = self.shortages(filesystem).into_iter()
+            .map(|(dirs, needed, available)| format!("  {} needed, {} available for {}",
+                progress::bytes(needed),
+                progress::bytes(available),
+                dirs.iter().map(|dir| dir.display().to_string()).collect::<Vec<_>>().join(", ")))
+            .collect();
+
+        if !shortages.is_empty() {
+            return Err(install_err(format!("not enough disk space, nothing was downloaded:\n{}\nfree some space, or run mpt-get autoclean to trim the cache",
+                shortages.join("\n"))).into());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortages() {
+        let mut needs = SpaceNeeds::default();
+        needs.add(Path::new("/srv/mirai/plugins"), 600);
+        needs.add(Path::new("/srv/mirai/plugin-shared-libraries"), 300);
+        needs.add(Path::new("/home/bot/.mpt-get/cache"), 600);
+        needs.add(Path::new("/srv/mirai/plugins"), 200);
+
+        let filesystem = |dir: &Path| Some(Filesystem { device: if dir.starts_with("/srv") { 1 } else { 2 }, available: 1000 });
+
+        assert_eq!(vec![(vec![Path::new("/srv/mirai/plugins"), Path::new("/srv/mirai/plugin-shared-libraries")], 1100, 1000)], needs.shortages(filesystem));
+        assert!(needs.shortages(|_| None).is_empty());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn statvfs() {
+        let dir = std::env::temp_dir();
+        let fs = filesystem(&dir.join("mpt-get-missing").join("plugins")).unwrap();
+
+        assert_eq!(Some(fs.device), filesystem(&dir).map(|fs| fs.device));
+        assert!(SpaceNeeds::default().check().is_ok());
+    }
+}
@@ -0,0 +1,117 @@
+use std::{collections::{BTreeMap, BTreeSet}, fs::{self, File}, io::BufReader, path::{Path, PathBuf}};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::io_err, index::PackageID, version::VersionReq};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct InstalledPackage {
+    pub version: String,
+    pub channel: String,
+    pub file: PathBuf,
+
+    /// Pulled in as a dependency of another package rather than installed explicitly.
+    #[serde(default)]
+    pub auto: bool,
+
+    /// Runtime libraries from the POM of the package.
+    #[serde(default)]
+    pub libraries: Vec<InstalledLibrary>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct InstalledLibrary {
+    pub id: PackageID,
+    pub version: String,
+    pub file: PathBuf
+}
+
+/// Everything mpt-get knows about the local installation, stored as `installed.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct InstalledState {
+    #[serde(default)]
+    pub packages: BTreeMap<PackageID, InstalledPackage>,
+
+    /// Packages that `upgrade` must leave alone.
+    #[serde(default)]
+    pub holds: BTreeSet<PackageID>,
+
+    /// Version constraints that `install` and `upgrade` must respect.
+    #[serde(default)]
+    pub pins: BTreeMap<PackageID, VersionReq>
+}
+
+impl InstalledState {
+    /**
+     * Load state from file, a missing file means nothing is installed yet.
+     */
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<InstalledState> {
+        let path = path.as_ref();
+
+        if !path.exists() {
+            return Ok(InstalledState::default());
+        }
+
+        let file = File::open(path)
+            .map_err(|_| io_err(format!("cannot open {:?}", path)))?;
+
+        let state = serde_json::from_reader(BufReader::new(file))
+            .map_err(|err| io_err(format!("failed to parse {:?}: {}", path, err)))?;
+
+        Ok(state)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // write to a sibling file first, so a crash never leaves a truncated state behind
+        let mut tmp = PathBuf::from(path);
+        tmp.set_extension("json.tmp");
+
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, path)?;
+
+        Ok(())
+    }
+
+    pub fn is_held(&self, pid: &PackageID) -> bool {
+        self.holds.contains(pid)
+    }
+
+    pub fn pin(&self, pid: &PackageID) -> Option<&VersionReq> {
+        self.pins.get(pid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip_state() {
+        let pid: PackageID = "net.mamoe:chat-command".parse().unwrap();
+        let mut state = InstalledState::default();
+
+        state.packages.insert(pid.clone(), InstalledPackage {
+            version: "0.3.0".to_string(),
+            channel: "stable".to_string(),
+            file: PathBuf::from("chat-command-0.3.0.jar"),
+            auto: false,
+            libraries: Vec::new()
+        });
+        state.holds.insert(pid.clone());
+        state.pins.insert(pid.clone(), "0.3.*".parse().unwrap());
+
+        let json = serde_json::to_string(&state).unwrap();
+        let state: InstalledState = serde_json::from_str(&json).unwrap();
+
+        assert!(state.is_held(&pid));
+        assert_eq!("0.3.*", state.pin(&pid).unwrap().to_string());
+        assert_eq!("0.3.0", state.packages[&pid].version);
+    }
+}
@@ -0,0 +1,268 @@
+use std::{cmp::Ordering, fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{parse_err, Error};
+
+/// A package version as it appears in the index, such as `2.4.0` or `2.0-RC`.
+///
+/// Numeric components are compared numerically (missing components count as `0`),
+/// and a version with a pre-release tag is older than the same version without one.
+#[derive(Debug, Clone)]
+pub struct Version {
+    pub parts: Vec<u64>,
+    pub pre: Option<String>,
+    raw: String
+}
+
+impl FromStr for Version {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        // anything after the numeric components is a qualifier: `2.0-RC`, `2.0.M1`
+        let idx = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+        let numbers = s[..idx].trim_end_matches('.');
+        let pre = s[idx..].trim_start_matches(['-', '.']);
+
+        if numbers.is_empty() {
+            return Err(parse_err(format!("invalid version '{}'", s)));
+        }
+
+        let parts = numbers
+            .split('.')
+            .map(|part| part.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| parse_err(format!("invalid version '{}'", s)))?;
+
+        Ok(Version {
+            parts,
+            pre: if pre.is_empty() { None } else { Some(pre.to_string()) },
+            raw: s.to_string()
+        })
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let len = self.parts.len().max(other.parts.len());
+
+        for i in 0..len {
+            let lhs = self.parts.get(i).copied().unwrap_or(0);
+            let rhs = other.parts.get(i).copied().unwrap_or(0);
+
+            match lhs.cmp(&rhs) {
+                Ordering::Equal => continue,
+                ord => return ord
+            }
+        }
+
+        match (&self.pre, &other.pre) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+            (Some(lhs), Some(rhs)) => lhs.cmp(rhs)
+        }
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    /// `1.x`, `0.3.*`: every listed component must match.
+    Wildcard
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Comparator {
+    op: Op,
+    version: Version
+}
+
+impl Comparator {
+    fn matches(&self, ver: &Version) -> bool {
+        match self.op {
+            Op::Eq => ver == &self.version,
+            Op::Gt => ver > &self.version,
+            Op::Ge => ver >= &self.version,
+            Op::Lt => ver < &self.version,
+            Op::Le => ver <= &self.version,
+            Op::Wildcard => self.version.parts
+                .iter()
+                .enumerate()
+                .all(|(i, part)| ver.parts.get(i).copied().unwrap_or(0) == *part)
+        }
+    }
+}
+
+impl FromStr for Comparator {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (op, rest) = if let Some(rest) = s.strip_prefix(">=") {
+            (Op::Ge, rest)
+        } else if let Some(rest) = s.strip_prefix("<=") {
+            (Op::Le, rest)
+        } else if let Some(rest) = s.strip_prefix('>') {
+            (Op::Gt, rest)
+        } else if let Some(rest) = s.strip_prefix('<') {
+            (Op::Lt, rest)
+        } else if let Some(rest) = s.strip_prefix('=') {
+            (Op::Eq, rest)
+        } else {
+            (Op::Eq, s)
+        };
+
+        let rest = rest.trim();
+        let is_wildcard = |part: &str| part == "*" || part == "x" || part == "X";
+
+        if rest.split('.').any(is_wildcard) {
+            if op != Op::Eq {
+                return Err(parse_err(format!("wildcard is not allowed in '{}'", s)));
+            }
+
+            let prefix = rest
+                .split('.')
+                .take_while(|part| !is_wildcard(part))
+                .collect::<Vec<_>>()
+                .join(".");
+
+            let version = if prefix.is_empty() {
+                Version { parts: Vec::new(), pre: None, raw: String::new() }
+            } else {
+                prefix.parse()?
+            };
+
+            Ok(Comparator { op: Op::Wildcard, version })
+        } else {
+            Ok(Comparator { op, version: rest.parse()? })
+        }
+    }
+}
+
+/// A version constraint such as `0.3.*`, `>=2`, `1.x` or `>=1.0, <2.0`.
+///
+/// Comma separated comparators must all hold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionReq {
+    comparators: Vec<Comparator>,
+    raw: String
+}
+
+impl VersionReq {
+    pub fn matches(&self, ver: &Version) -> bool {
+        self.comparators.iter().all(|cmp| cmp.matches(ver))
+    }
+
+    /// Like `matches`, but versions that fail to parse never match.
+    pub fn matches_str<S: AsRef<str>>(&self, ver: S) -> bool {
+        ver.as_ref().parse().map(|ver| self.matches(&ver)).unwrap_or(false)
+    }
+}
+
+impl FromStr for VersionReq {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let comparators = s
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(VersionReq {
+            comparators,
+            raw: s.trim().to_string()
+        })
+    }
+}
+
+impl Display for VersionReq {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+impl Serialize for VersionReq {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer {
+        serializer.serialize_str(&self.raw)
+    }
+}
+
+impl <'de> Deserialize<'de> for VersionReq {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de> {
+        let raw = String::deserialize(deserializer)?;
+
+        raw.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ver(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    fn req(s: &str) -> VersionReq {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn compare_versions() {
+        assert!(ver("2.4.0") > ver("2.3.10"));
+        assert!(ver("2.4") == ver("2.4.0"));
+        assert!(ver("2.0-RC") < ver("2.0"));
+        assert!(ver("1.9.8") < ver("1.10.0"));
+        assert!(ver("2.0.M1") < ver("2.0"));
+    }
+
+    #[test]
+    fn wildcard_req() {
+        assert!(req("0.3.*").matches(&ver("0.3.2")));
+        assert!(!req("0.3.*").matches(&ver("0.4.0")));
+        assert!(req("1.x").matches(&ver("1.9.0")));
+        assert!(req("*").matches(&ver("5.0")));
+    }
+
+    #[test]
+    fn range_req() {
+        let range = req(">=1.0, <2.0");
+
+        assert!(range.matches(&ver("1.0")));
+        assert!(range.matches(&ver("1.99.1")));
+        assert!(!range.matches(&ver("2.0")));
+        assert!(req("2.4.0").matches(&ver("2.4")));
+        assert!("<=".parse::<VersionReq>().is_err());
+    }
+}